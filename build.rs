@@ -0,0 +1,43 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+/// Embeds the git commit the binary was built from as `UPNOTIF_GIT_SHA`.
+///
+/// Builds without a git checkout (such as the Docker image) can pass the commit in the
+/// `UPNOTIF_GIT_SHA` environment variable instead; otherwise it is left empty.
+fn main() {
+    println!("cargo:rerun-if-env-changed=UPNOTIF_GIT_SHA");
+
+    let sha = env::var("UPNOTIF_GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.trim().is_empty())
+        .or_else(git_sha)
+        .unwrap_or_default();
+    println!("cargo:rustc-env=UPNOTIF_GIT_SHA={}", sha.trim());
+
+    // Rebuild when the checked-out commit changes
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    if let Ok(head) = fs::read_to_string(".git/HEAD") {
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            // Refs that have not been updated since `git gc` only live in packed-refs
+            let loose = format!(".git/{}", reference);
+            if fs::metadata(&loose).is_ok() {
+                println!("cargo:rerun-if-changed={}", loose);
+            } else {
+                println!("cargo:rerun-if-changed=.git/packed-refs");
+            }
+        }
+    }
+}
+
+fn git_sha() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
@@ -0,0 +1,66 @@
+//! Periodic operational summary of the checks the monitor ran, independent of the uptime digest.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Checks of all URLs since the last activity summary.
+#[derive(Debug, Clone, Default)]
+pub struct ActivityWindow {
+    pub checks: u64,
+    pub failed: u64,
+    latency_total_ms: u64,
+    latency_count: u64,
+    /// Failed checks by failure category, such as `timeout` or `HTTP 503`.
+    pub failures: BTreeMap<String, u64>,
+}
+
+impl ActivityWindow {
+    /// Records one check; `failure` is the category of a check that reported the URL DOWN.
+    pub fn record(&mut self, failure: Option<String>, latency: Option<Duration>) {
+        self.checks += 1;
+        match failure {
+            Some(category) => {
+                self.failed += 1;
+                *self.failures.entry(category).or_default() += 1;
+            }
+            None => {
+                if let Some(latency) = latency {
+                    self.latency_total_ms += latency.as_millis() as u64;
+                    self.latency_count += 1;
+                }
+            }
+        }
+    }
+
+    /// Average response time of the successful checks.
+    fn average_latency_ms(&self) -> Option<u64> {
+        (self.latency_count > 0).then(|| self.latency_total_ms / self.latency_count)
+    }
+}
+
+/// Renders the summary, with the failure categories from most to least frequent.
+pub fn render(title: &str, window: &ActivityWindow) -> String {
+    let mut text = format!(
+        "{}\n{} checks: {} succeeded, {} failed",
+        title,
+        window.checks,
+        window.checks - window.failed,
+        window.failed
+    );
+    if let Some(average) = window.average_latency_ms() {
+        text.push_str(&format!(", average response time {}ms", average));
+    }
+
+    let mut failures: Vec<(&String, &u64)> = window.failures.iter().collect();
+    failures.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    if failures.is_empty() {
+        text.push_str("\nNo errors");
+    } else {
+        let breakdown: Vec<String> = failures
+            .iter()
+            .map(|(category, count)| format!("{} {}", category, count))
+            .collect();
+        text.push_str(&format!("\nErrors: {}", breakdown.join(", ")));
+    }
+    text
+}
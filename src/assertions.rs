@@ -0,0 +1,163 @@
+//! Numeric assertions on fields of a JSON response, such as `$.queue_depth < 1000`.
+//!
+//! Paths support the common JSONPath subset of object keys and array indices:
+//! `$.checks[0].latency_ms` or `$.db.pool.in_use`.
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Operator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+        }
+    }
+
+    fn holds(&self, actual: f64, expected: f64) -> bool {
+        match self {
+            Operator::Less => actual < expected,
+            Operator::LessOrEqual => actual <= expected,
+            Operator::Greater => actual > expected,
+            Operator::GreaterOrEqual => actual >= expected,
+            Operator::Equal => actual == expected,
+            Operator::NotEqual => actual != expected,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A parsed `$.a.b[0]` path.
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    path: String,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Option<Self> {
+        Some(Self {
+            path: path.to_string(),
+            segments: parse_path(path)?,
+        })
+    }
+
+    /// The value at this path, if the document has one.
+    pub fn find<'a>(&self, document: &'a Value) -> Option<&'a Value> {
+        self.segments.iter().try_fold(document, |value, segment| match segment {
+            Segment::Key(key) => value.get(key),
+            Segment::Index(index) => value.get(index),
+        })
+    }
+}
+
+impl std::fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    path: JsonPath,
+    operator: Operator,
+    expected: f64,
+}
+
+impl Assertion {
+    /// Parses `<path> <operator> <number>`, e.g. `$.error_rate < 0.05`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid assertion '{}', expected e.g. '$.queue_depth < 1000'", value);
+
+        // Two-character operators first so `<=` isn't read as `<`
+        let operators = [
+            ("<=", Operator::LessOrEqual),
+            (">=", Operator::GreaterOrEqual),
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ];
+        let (path, operator, expected) = operators
+            .iter()
+            .find_map(|(symbol, operator)| {
+                let (path, expected) = value.split_once(symbol)?;
+                Some((path.trim(), *operator, expected.trim()))
+            })
+            .ok_or_else(invalid)?;
+
+        let expected: f64 = expected.parse().map_err(|_| invalid())?;
+        let path = JsonPath::parse(path).ok_or_else(invalid)?;
+
+        Ok(Self {
+            path,
+            operator,
+            expected,
+        })
+    }
+
+    /// Checks the assertion against a document, describing the failure if it doesn't hold.
+    pub fn evaluate(&self, document: &Value) -> Result<(), String> {
+        let value = self
+            .path
+            .find(document)
+            .ok_or_else(|| format!("{} is missing", self.path))?;
+        let actual = value
+            .as_f64()
+            .ok_or_else(|| format!("{} is not a number: {}", self.path, value))?;
+
+        if self.operator.holds(actual, self.expected) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} is {}, expected {} {}",
+                self.path,
+                value,
+                self.operator.symbol(),
+                self.expected
+            ))
+        }
+    }
+}
+
+/// Splits `$.a.b[0]` into its segments.
+fn parse_path(path: &str) -> Option<Vec<Segment>> {
+    let mut rest = path.strip_prefix('$')?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            let key = &after_dot[..end];
+            if key.is_empty() {
+                return None;
+            }
+            segments.push(Segment::Key(key.to_string()));
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            let (index, after) = after_bracket.split_once(']')?;
+            segments.push(Segment::Index(index.trim().parse().ok()?));
+            rest = after;
+        } else {
+            return None;
+        }
+    }
+    Some(segments)
+}
@@ -0,0 +1,205 @@
+//! Control endpoint for temporary per-URL deploy windows, served over plain HTTP.
+//!
+//! A deploy pipeline can mute the alerts of the URL it is about to deploy with
+//! `POST /deploy-windows?url=<url or name>&minutes=10`, end the window early with `DELETE` on the
+//! same path, and list the active windows with `GET /deploy-windows`. Windows are kept in memory
+//! only, so a restart ends them.
+
+use crate::metrics::read_request_head;
+use chrono::{DateTime, SecondsFormat};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use url::form_urlencoded;
+
+/// Longest deploy window, so a pipeline that never ends its window doesn't mute a URL for good.
+pub const MAX_WINDOW_MINUTES: u64 = 24 * 60;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A monitored URL as the control endpoint can refer to it.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub url: String,
+    pub name: Option<String>,
+}
+
+/// Active deploy windows by URL, shared between the monitor and the control endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct DeployWindows {
+    /// Unix time each window ends.
+    windows: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl DeployWindows {
+    /// When the deploy window of `url` ends, if one is active at `now`.
+    pub fn active(&self, url: &str, now: u64) -> Option<u64> {
+        let windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        windows.get(url).copied().filter(|until| *until > now)
+    }
+
+    /// Every window active at `now`; expired ones are forgotten.
+    pub fn all(&self, now: u64) -> BTreeMap<String, u64> {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        windows.retain(|_, until| *until > now);
+        windows.clone()
+    }
+
+    fn open(&self, url: &str, until: u64) {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        windows.insert(url.to_string(), until);
+    }
+
+    fn close(&self, url: &str) -> bool {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        windows.remove(url).is_some()
+    }
+
+    /// Starts serving the control endpoint on `address` in the background.
+    pub fn serve(&self, address: SocketAddr, token: Option<String>, targets: Vec<Target>) -> io::Result<()> {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+
+        let endpoint = Arc::new(Endpoint {
+            windows: self.clone(),
+            token,
+            targets,
+        });
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(respond(stream, endpoint.clone()));
+                    }
+                    Err(e) => {
+                        // Usually out of file descriptors, so wait instead of spinning
+                        log::warn!("Failed to accept control connection: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+struct Endpoint {
+    windows: DeployWindows,
+    /// Bearer token every request must carry, when set.
+    token: Option<String>,
+    targets: Vec<Target>,
+}
+
+impl Endpoint {
+    fn handle(&self, head: &str) -> (&'static str, String) {
+        let mut lines = head.lines();
+        let mut parts = lines.next().unwrap_or_default().split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return ("400 Bad Request", error("malformed request"));
+        };
+
+        if let Some(token) = &self.token {
+            let authorized = lines
+                .filter_map(|line| line.split_once(':'))
+                .filter(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+                .any(|(_, value)| value.trim().strip_prefix("Bearer ") == Some(token.as_str()));
+            if !authorized {
+                return ("401 Unauthorized", error("missing or wrong bearer token"));
+            }
+        }
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        if path != "/deploy-windows" {
+            return ("404 Not Found", error("not found, deploy windows are at /deploy-windows"));
+        }
+        let params: BTreeMap<String, String> = form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+        let now = unix_now();
+
+        match method {
+            "GET" => {
+                let windows: Vec<_> = self
+                    .windows
+                    .all(now)
+                    .into_iter()
+                    .map(|(url, until)| window_json(&url, until))
+                    .collect();
+                ("200 OK", json!({ "deploy_windows": windows }).to_string())
+            }
+            "POST" | "DELETE" => {
+                let Some(wanted) = params.get("url") else {
+                    return ("400 Bad Request", error("url is required"));
+                };
+                let Some(url) = self.resolve(wanted) else {
+                    return ("404 Not Found", error(&format!("{} is not a monitored URL or name", wanted)));
+                };
+
+                if method == "DELETE" {
+                    if self.windows.close(url) {
+                        log::info!("Deploy window of {} ended through the control endpoint", url);
+                    }
+                    return ("200 OK", json!({ "url": url, "deploy_window": null }).to_string());
+                }
+
+                let minutes = params
+                    .get("minutes")
+                    .and_then(|minutes| minutes.parse::<u64>().ok())
+                    .filter(|minutes| (1..=MAX_WINDOW_MINUTES).contains(minutes));
+                let Some(minutes) = minutes else {
+                    let message = format!("minutes must be a number from 1 to {}", MAX_WINDOW_MINUTES);
+                    return ("400 Bad Request", error(&message));
+                };
+                let until = now + minutes * 60;
+                self.windows.open(url, until);
+                log::info!("Suppressing notifications of {} for {} minutes, a deploy window was opened", url, minutes);
+                ("200 OK", window_json(url, until).to_string())
+            }
+            _ => ("405 Method Not Allowed", error("only GET, POST and DELETE are supported")),
+        }
+    }
+
+    /// Finds a monitored URL by its URL or its name.
+    fn resolve(&self, wanted: &str) -> Option<&str> {
+        self.targets
+            .iter()
+            .find(|target| target.url == wanted || target.name.as_deref() == Some(wanted))
+            .map(|target| target.url.as_str())
+    }
+}
+
+fn window_json(url: &str, until: u64) -> serde_json::Value {
+    let until_utc = DateTime::from_timestamp(until as i64, 0)
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default();
+    json!({ "url": url, "until": until, "until_utc": until_utc })
+}
+
+fn error(message: &str) -> String {
+    json!({ "error": message }).to_string()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Answers one control request and closes the connection.
+async fn respond(mut stream: TcpStream, endpoint: Arc<Endpoint>) {
+    let Ok(Some(head)) = timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await else {
+        return;
+    };
+
+    let (status, body) = endpoint.handle(&head);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+        status,
+        body.len() + 1,
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
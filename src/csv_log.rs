@@ -0,0 +1,83 @@
+//! Flat-file log with one row per URL per check cycle, for spreadsheets and quick scripts.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const COLUMNS: [&str; 5] = ["timestamp", "url", "status", "latency_ms", "status_code"];
+
+pub struct CsvRow<'a> {
+    pub timestamp: String,
+    pub url: &'a str,
+    pub status: String,
+    pub latency_ms: Option<u64>,
+    pub status_code: Option<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvLog {
+    path: PathBuf,
+    delimiter: char,
+}
+
+impl CsvLog {
+    /// Files ending in `.tsv` are tab-separated, everything else is comma-separated.
+    pub fn new(path: PathBuf) -> Self {
+        let tsv = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("tsv"));
+        Self {
+            path,
+            delimiter: if tsv { '\t' } else { ',' },
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends rows, writing the header first if the file is new or empty.
+    pub fn append(&self, rows: &[CsvRow]) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+
+        let mut out = String::new();
+        if file.metadata()?.len() == 0 {
+            self.push_record(&mut out, COLUMNS.iter().map(|column| column.to_string()));
+        }
+        for row in rows {
+            self.push_record(
+                &mut out,
+                [
+                    row.timestamp.clone(),
+                    row.url.to_string(),
+                    row.status.clone(),
+                    row.latency_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+                    row.status_code.map(|code| code.to_string()).unwrap_or_default(),
+                ]
+                .into_iter(),
+            );
+        }
+
+        // A single write keeps a cycle's rows together if several processes share the file
+        file.write_all(out.as_bytes())
+    }
+
+    fn push_record(&self, out: &mut String, fields: impl Iterator<Item = String>) {
+        for (index, field) in fields.enumerate() {
+            if index > 0 {
+                out.push(self.delimiter);
+            }
+            out.push_str(&self.escape(&field));
+        }
+        out.push_str("\r\n");
+    }
+
+    /// Quotes a field per RFC 4180 if it contains the delimiter, a quote or a line break.
+    fn escape(&self, field: &str) -> String {
+        if field.contains([self.delimiter, '"', '\r', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+}
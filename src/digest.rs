@@ -0,0 +1,103 @@
+//! Periodic summary of uptime and response time percentiles per URL.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Checks collected for one URL since the last digest.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DigestWindow {
+    pub checks: u64,
+    pub up_checks: u64,
+    /// Response times of successful checks in milliseconds, kept in full so percentiles are exact.
+    pub latencies_ms: Vec<u64>,
+    /// Times to first byte of successful checks, for URLs that measure them.
+    pub ttfbs_ms: Vec<u64>,
+}
+
+impl DigestWindow {
+    pub fn record(&mut self, available: bool, latency: Option<Duration>, ttfb: Option<Duration>) {
+        self.checks += 1;
+        if available {
+            self.up_checks += 1;
+            if let Some(latency) = latency {
+                self.latencies_ms.push(latency.as_millis() as u64);
+            }
+            if let Some(ttfb) = ttfb {
+                self.ttfbs_ms.push(ttfb.as_millis() as u64);
+            }
+        }
+    }
+}
+
+/// One line of the digest table.
+pub struct DigestRow<'a> {
+    pub name: &'a str,
+    pub window: &'a DigestWindow,
+}
+
+/// Nearest-rank percentile of already sorted values.
+fn percentile(sorted: &[u64], percent: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Renders the digest as a fixed-width table inside a code block so columns line up in Slack.
+///
+/// Time to first byte columns are only added when some URL measured it.
+pub fn render(title: &str, rows: &[DigestRow]) -> String {
+    let format_ms = |value: Option<u64>| value.map_or_else(|| "-".to_string(), |ms| format!("{}ms", ms));
+    let with_ttfb = rows.iter().any(|row| !row.window.ttfbs_ms.is_empty());
+
+    let mut header: Vec<String> = ["URL", "Uptime", "p50", "p95", "p99"].map(str::to_string).to_vec();
+    if with_ttfb {
+        header.extend(["TTFB p50".to_string(), "TTFB p95".to_string()]);
+    }
+    let mut lines = vec![header];
+    for row in rows {
+        let mut sorted = row.window.latencies_ms.clone();
+        sorted.sort_unstable();
+        let uptime = if row.window.checks == 0 {
+            "-".to_string()
+        } else {
+            format!("{:.2}%", row.window.up_checks as f64 / row.window.checks as f64 * 100.0)
+        };
+        let mut line = vec![
+            row.name.to_string(),
+            uptime,
+            format_ms(percentile(&sorted, 50.0)),
+            format_ms(percentile(&sorted, 95.0)),
+            format_ms(percentile(&sorted, 99.0)),
+        ];
+        if with_ttfb {
+            let mut ttfbs = row.window.ttfbs_ms.clone();
+            ttfbs.sort_unstable();
+            line.extend([format_ms(percentile(&ttfbs, 50.0)), format_ms(percentile(&ttfbs, 95.0))]);
+        }
+        lines.push(line);
+    }
+
+    let mut widths = vec![0; lines[0].len()];
+    for line in &lines {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let table: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            let name = format!("{:<width$}", line[0], width = widths[0]);
+            let values: Vec<String> = line[1..]
+                .iter()
+                .zip(&widths[1..])
+                .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+                .collect();
+            format!("{}  {}", name, values.join("  "))
+        })
+        .collect();
+
+    format!("{}\n```\n{}\n```", title, table.join("\n"))
+}
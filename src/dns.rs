@@ -0,0 +1,189 @@
+//! Name resolution through configured DNS servers instead of the system resolver.
+//!
+//! Only A and AAAA lookups are needed, so queries are encoded by hand and sent over UDP
+//! instead of pulling in a full resolver. Hosts without a configured server use the system
+//! resolver, which keeps notifications and other unmonitored hosts on the normal path.
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// Large enough for any UDP answer a server sends without EDNS.
+const MAX_RESPONSE_LEN: usize = 4096;
+
+/// Parses `10.0.0.53` or `10.0.0.53:5353`, defaulting to port 53.
+pub fn parse_server(value: &str) -> Option<SocketAddr> {
+    let value = value.trim();
+    value
+        .parse::<SocketAddr>()
+        .ok()
+        .or_else(|| value.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, 53)))
+}
+
+/// Resolves monitored hosts through their configured DNS server.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    /// DNS server per lowercase host name.
+    servers: Arc<HashMap<String, SocketAddr>>,
+    timeout: Duration,
+}
+
+impl Resolver {
+    pub fn new(servers: HashMap<String, SocketAddr>, timeout: Duration) -> Self {
+        Self {
+            servers: Arc::new(servers),
+            timeout,
+        }
+    }
+
+    /// Whether any host uses a custom server, i.e. whether HTTP clients need this resolver.
+    pub fn is_custom(&self) -> bool {
+        !self.servers.is_empty()
+    }
+
+    /// All addresses of `host`, from its configured server or the system resolver.
+    pub async fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+        let Some(server) = self.servers.get(&host.to_lowercase()) else {
+            return Ok(lookup_host((host, 0)).await?.map(|address| address.ip()).collect());
+        };
+
+        let (v4, v6) = tokio::join!(
+            query(*server, host, TYPE_A, self.timeout),
+            query(*server, host, TYPE_AAAA, self.timeout)
+        );
+        let addresses: Vec<IpAddr> = match (v4, v6) {
+            (Err(e), Err(_)) => return Err(io::Error::other(format!("{} via {}: {}", host, server, e))),
+            (v4, v6) => v4.unwrap_or_default().into_iter().chain(v6.unwrap_or_default()).collect(),
+        };
+        if addresses.is_empty() {
+            return Err(io::Error::other(format!("{} via {}: no addresses", host, server)));
+        }
+        Ok(addresses)
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addresses = resolver.lookup(&host).await?;
+            // The connector fills in the port of the URL
+            let addrs: Addrs = Box::new(addresses.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+/// Sends one query to `server` and returns the addresses of type `record_type` in the answer.
+async fn query(server: SocketAddr, host: &str, record_type: u16, wait: Duration) -> Result<Vec<IpAddr>, String> {
+    let id: u16 = rand::random();
+    let request = encode_query(id, host, record_type).ok_or_else(|| format!("invalid host name {}", host))?;
+
+    let local: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).await.map_err(|e| e.to_string())?;
+    socket.connect(server).await.map_err(|e| e.to_string())?;
+    socket.send(&request).await.map_err(|e| e.to_string())?;
+
+    let exchange = async {
+        let mut buf = vec![0; MAX_RESPONSE_LEN];
+        loop {
+            let len = socket.recv(&mut buf).await.map_err(|e| e.to_string())?;
+            // Ignore stray datagrams, e.g. late answers to an earlier query
+            if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+                return parse_response(&buf[..len], record_type);
+            }
+        }
+    };
+    timeout(wait, exchange).await.map_err(|_| "DNS query timed out".to_string())?
+}
+
+fn encode_query(id: u16, host: &str, record_type: u16) -> Option<Vec<u8>> {
+    let mut packet = Vec::with_capacity(host.len() + 18);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Standard query with recursion desired, one question
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return None;
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&record_type.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Some(packet)
+}
+
+fn parse_response(packet: &[u8], record_type: u16) -> Result<Vec<IpAddr>, String> {
+    let malformed = || "malformed DNS response".to_string();
+    let read_u16 = |pos: usize| -> Option<u16> { Some(u16::from_be_bytes([*packet.get(pos)?, *packet.get(pos + 1)?])) };
+
+    let flags = read_u16(2).ok_or_else(malformed)?;
+    if flags & 0x0200 != 0 {
+        return Err("DNS response truncated".to_string());
+    }
+    match flags & 0x000f {
+        0 => {}
+        3 => return Err("no such host".to_string()),
+        code => return Err(format!("DNS server returned error code {}", code)),
+    }
+    let questions = read_u16(4).ok_or_else(malformed)?;
+    let answers = read_u16(6).ok_or_else(malformed)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(packet, pos).ok_or_else(malformed)? + 4;
+    }
+
+    // CNAME records in the chain are skipped; only the final addresses matter
+    let mut addresses = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(packet, pos).ok_or_else(malformed)?;
+        let answer_type = read_u16(pos).ok_or_else(malformed)?;
+        let len = read_u16(pos + 8).ok_or_else(malformed)? as usize;
+        let data = packet.get(pos + 10..pos + 10 + len).ok_or_else(malformed)?;
+        match (answer_type, data.len()) {
+            (TYPE_A, 4) if answer_type == record_type => {
+                addresses.push(IpAddr::from(<[u8; 4]>::try_from(data).map_err(|_| malformed())?));
+            }
+            (TYPE_AAAA, 16) if answer_type == record_type => {
+                addresses.push(IpAddr::from(<[u8; 16]>::try_from(data).map_err(|_| malformed())?));
+            }
+            _ => {}
+        }
+        pos += 10 + len;
+    }
+    Ok(addresses)
+}
+
+/// Position just past the (possibly compressed) domain name starting at `pos`.
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            // A pointer ends the name
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
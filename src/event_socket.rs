@@ -0,0 +1,115 @@
+//! Status events for co-located tools, written as newline-delimited JSON to a Unix domain socket.
+//!
+//! The monitor listens on the socket and every connected consumer receives the events published
+//! after it connected. Nothing is kept for consumers that connect later, and a consumer that falls
+//! more than `BUFFERED_EVENTS` behind skips the oldest events instead of holding up the checks.
+
+use crate::notifier::StatusEvent;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+const BUFFERED_EVENTS: usize = 256;
+
+pub struct EventSocket {
+    path: PathBuf,
+    sender: broadcast::Sender<Arc<str>>,
+}
+
+impl EventSocket {
+    /// Listens on `path`, replacing a socket file left behind by a monitor that is no longer running.
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        remove_stale_socket(path)?;
+        let listener = tokio::net::UnixListener::bind(path)?;
+        let (sender, _) = broadcast::channel(BUFFERED_EVENTS);
+        tokio::spawn(accept(listener, sender.clone()));
+        Ok(Self {
+            path: path.to_path_buf(),
+            sender,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain sockets are not available on this platform",
+        ))
+    }
+
+    /// Queues an event for every connected consumer; without consumers it is dropped.
+    pub fn publish(&self, event: &StatusEvent) {
+        let Ok(mut line) = serde_json::to_string(event) else {
+            return;
+        };
+        line.push('\n');
+        // Only fails when no consumer is connected
+        let _ = self.sender.send(Arc::from(line));
+    }
+}
+
+impl Drop for EventSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "another process is already listening on it",
+                ));
+            }
+            fs::remove_file(path)
+        }
+        Ok(_) => Err(io::Error::new(io::ErrorKind::AlreadyExists, "the path exists and is not a socket")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+async fn accept(listener: tokio::net::UnixListener, sender: broadcast::Sender<Arc<str>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                log::debug!("Event consumer connected");
+                tokio::spawn(forward(stream, sender.subscribe()));
+            }
+            Err(e) => {
+                // Usually out of file descriptors, so wait instead of spinning
+                log::warn!("Failed to accept event consumer: {}", e);
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn forward(mut stream: tokio::net::UnixStream, mut events: broadcast::Receiver<Arc<str>>) {
+    use tokio::io::AsyncWriteExt;
+
+    loop {
+        match events.recv().await {
+            Ok(line) => {
+                if stream.write_all(line.as_bytes()).await.is_err() {
+                    log::debug!("Event consumer disconnected");
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                log::warn!("Event consumer is not keeping up, skipped {} events", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Turns checks of individual URLs on and off from an external feature-flag endpoint.
+//!
+//! The endpoint returns a JSON object mapping flag names to booleans, e.g.
+//! `{"monitor-payments": true, "monitor-search": false}`. The last answer is kept, so an outage of
+//! the flag service leaves every URL as it was instead of starting or stopping checks.
+
+use log::warn;
+use reqwest::Client;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub struct FlagSource {
+    url: String,
+    refresh: Duration,
+    states: HashMap<String, bool>,
+    /// Whether any fetch succeeded yet; until then every flag counts as on.
+    loaded: bool,
+    last_attempt: Option<Instant>,
+}
+
+impl FlagSource {
+    pub fn new(url: String, refresh: Duration) -> Self {
+        Self {
+            url,
+            refresh,
+            states: HashMap::new(),
+            loaded: false,
+            last_attempt: None,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.last_attempt.is_none_or(|at| at.elapsed() >= self.refresh)
+    }
+
+    /// Whether URLs behind `name` are checked. A flag missing from the answer is off, but before the
+    /// first successful fetch everything is checked so a flag service outage at startup isn't silent.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.loaded || self.states.get(name).copied().unwrap_or(false)
+    }
+
+    /// Fetches the flags in `names` and returns those whose state changed. On failure the last
+    /// known states stay in effect.
+    pub async fn refresh(&mut self, client: &Client, names: &BTreeSet<&str>) -> Result<Vec<(String, bool)>, String> {
+        self.last_attempt = Some(Instant::now());
+
+        let response = client
+            .get(&self.url)
+            .send()
+            .await
+            .map_err(|e| format!("request failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("returned status {}", response.status()));
+        }
+        let flags: serde_json::Map<String, serde_json::Value> = response
+            .json()
+            .await
+            .map_err(|_| "response is not a JSON object".to_string())?;
+
+        let mut changed = Vec::new();
+        for name in names {
+            let previous = self.is_enabled(name);
+            let enabled = match flags.get(*name) {
+                Some(serde_json::Value::Bool(enabled)) => *enabled,
+                None => false,
+                Some(value) => {
+                    warn!("Ignoring flag {}, expected true or false but got {}", name, value);
+                    previous
+                }
+            };
+            self.states.insert(name.to_string(), enabled);
+            if previous != enabled {
+                changed.push((name.to_string(), enabled));
+            }
+        }
+        self.loaded = true;
+        Ok(changed)
+    }
+}
@@ -0,0 +1,184 @@
+//! Minimal client for the standard gRPC Health Checking Protocol (`grpc.health.v1.Health/Check`).
+//!
+//! The request and response messages are tiny, so they are encoded by hand on top of `h2`
+//! instead of pulling in a full gRPC stack and protobuf code generation.
+
+use crate::tls;
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncWrite};
+use url::Url;
+
+const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// `ServingStatus` values from the health protocol definition.
+const SERVING: u64 = 1;
+
+/// Queries the health service of a `grpc://` (plaintext) or `grpcs://` (TLS) target.
+///
+/// The URL path selects the service name, e.g. `grpc://host:50051/my.package.Service`;
+/// an empty path asks for the overall health of the server.
+pub async fn check(url: &Url, dialer: &tls::Dialer) -> Result<(), String> {
+    let host = url.host_str().ok_or("missing host")?;
+    let tls = url.scheme() == "grpcs";
+    let port = url.port().unwrap_or(if tls { 443 } else { 80 });
+    let service = url.path().trim_start_matches('/');
+
+    if tls {
+        let stream = tls::connect(&tls::connector(&[b"h2"]), host, port, dialer).await?;
+        health_check(stream, url.scheme(), host, port, service).await
+    } else {
+        let tcp = tls::connect_tcp(host, port, dialer).await?;
+        health_check(tcp, url.scheme(), host, port, service).await
+    }
+}
+
+async fn health_check<S>(stream: S, scheme: &str, host: &str, port: u16, service: &str) -> Result<(), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (client, connection) = h2::client::handshake(stream)
+        .await
+        .map_err(|e| format!("HTTP/2 handshake failed: {}", e))?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    let mut client = client.ready().await.map_err(|e| format!("HTTP/2 error: {}", e))?;
+
+    let http_scheme = if scheme == "grpcs" { "https" } else { "http" };
+    let request = http::Request::builder()
+        .method("POST")
+        .uri(format!("{}://{}:{}{}", http_scheme, host, port, HEALTH_CHECK_PATH))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(())
+        .map_err(|e| format!("invalid request: {}", e))?;
+
+    let (response, mut send) = client
+        .send_request(request, false)
+        .map_err(|e| format!("HTTP/2 error: {}", e))?;
+    send.send_data(encode_request(service), true)
+        .map_err(|e| format!("HTTP/2 error: {}", e))?;
+
+    let response = response.await.map_err(|e| format!("HTTP/2 error: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP status {}", response.status()));
+    }
+
+    // A "trailers-only" response carries grpc-status in the headers
+    let header_status = grpc_status(response.headers());
+    let mut body = response.into_body();
+
+    let mut message = BytesMut::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| format!("HTTP/2 error: {}", e))?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        message.extend_from_slice(&chunk);
+    }
+
+    let trailers = body.trailers().await.map_err(|e| format!("HTTP/2 error: {}", e))?;
+    let status = trailers.as_ref().and_then(grpc_status).or(header_status);
+
+    match status {
+        Some((0, _)) | None => {}
+        Some((code, Some(message))) => return Err(format!("gRPC error {}: {}", code, message)),
+        Some((code, None)) => return Err(format!("gRPC error {}", code)),
+    }
+
+    match decode_response(&message)? {
+        SERVING => Ok(()),
+        status => Err(format!("health status {}", serving_status_name(status))),
+    }
+}
+
+fn grpc_status(headers: &http::HeaderMap) -> Option<(u32, Option<String>)> {
+    let code = headers.get("grpc-status")?.to_str().ok()?.parse().ok()?;
+    let message = headers
+        .get("grpc-message")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    Some((code, message))
+}
+
+/// Frames a `HealthCheckRequest { service }` as a length-prefixed gRPC message.
+fn encode_request(service: &str) -> Bytes {
+    let mut message = BytesMut::new();
+    if !service.is_empty() {
+        message.put_u8(0x0a); // field 1, length-delimited
+        put_varint(&mut message, service.len() as u64);
+        message.put_slice(service.as_bytes());
+    }
+
+    let mut frame = BytesMut::with_capacity(5 + message.len());
+    frame.put_u8(0); // uncompressed
+    frame.put_u32(message.len() as u32);
+    frame.put_slice(&message);
+    frame.freeze()
+}
+
+/// Extracts `HealthCheckResponse.status` from a length-prefixed gRPC message.
+fn decode_response(frame: &[u8]) -> Result<u64, String> {
+    if frame.len() < 5 {
+        return Err("empty gRPC response".to_string());
+    }
+    if frame[0] != 0 {
+        return Err("compressed gRPC responses are not supported".to_string());
+    }
+
+    let length = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+    let mut message = frame
+        .get(5..5 + length)
+        .ok_or("truncated gRPC response")?;
+
+    // Proto3 omits default values, so a missing status field means UNKNOWN (0)
+    let mut status = 0;
+    while !message.is_empty() {
+        let key = read_varint(&mut message)?;
+        match (key >> 3, key & 0x7) {
+            (1, 0) => status = read_varint(&mut message)?,
+            (_, 0) => {
+                read_varint(&mut message)?;
+            }
+            (_, 1) => message = message.get(8..).ok_or("truncated gRPC response")?,
+            (_, 5) => message = message.get(4..).ok_or("truncated gRPC response")?,
+            (_, 2) => {
+                let length = read_varint(&mut message)? as usize;
+                message = message.get(length..).ok_or("truncated gRPC response")?;
+            }
+            (_, wire_type) => return Err(format!("unexpected protobuf wire type {}", wire_type)),
+        }
+    }
+
+    Ok(status)
+}
+
+fn put_varint(buf: &mut BytesMut, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first().ok_or("truncated gRPC response")?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("invalid protobuf varint".to_string())
+}
+
+fn serving_status_name(status: u64) -> &'static str {
+    match status {
+        0 => "UNKNOWN",
+        1 => "SERVING",
+        2 => "NOT_SERVING",
+        3 => "SERVICE_UNKNOWN",
+        _ => "unrecognized",
+    }
+}
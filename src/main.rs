@@ -1,15 +1,82 @@
-use log::{error, info};
-use reqwest::Client;
-use serde_json::json;
-use std::collections::HashMap;
+mod activity;
+mod assertions;
+mod control;
+mod csv_log;
+mod digest;
+mod dns;
+mod event_socket;
+mod flags;
+mod grpc;
+mod metrics;
+mod notifier;
+mod ocsp;
+mod oauth;
+mod otel;
+mod outbox;
+mod provenance;
+mod routing;
+mod rules;
+mod signing;
+mod snapshot;
+mod state;
+mod tls;
+mod token_file;
+mod wasm_check;
+
+use activity::ActivityWindow;
+use assertions::{Assertion, JsonPath};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use control::DeployWindows;
+use csv_log::{CsvLog, CsvRow};
+use digest::{DigestRow, DigestWindow};
+use event_socket::EventSocket;
+use flags::FlagSource;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::future::join_all;
+use log::{debug, error, info, warn};
+use metrics::Metrics;
+use notifier::{
+    EventWebhook, Incident, Mention, Notification, Notifier, Overflow, RateLimit, Severity, SeverityColors, StatusEvent, Throttle,
+};
+use oauth::{OAuthConfig, TokenProvider};
+use ocsp::Stapling;
+use otel::{CheckTrace, OtlpConfig, Span, Tracer};
+use outbox::{PersistedNotification, PersistedOutbox, OUTBOX_VERSION};
+use provenance::{FileSources, OverlayChange};
+use rand::seq::SliceRandom;
+use regex::Regex;
+use reqwest::header::{HeaderName, HeaderValue, SET_COOKIE};
+use reqwest::{Client, Method, Proxy, RequestBuilder, StatusCode};
+use routing::Route;
+use rules::{AlertRule, Transition};
+use serde::Deserialize;
+use signing::{RequestSigner, SignatureConfig};
+use snapshot::{Snapshot, SnapshotTarget, UrlSnapshot, SNAPSHOT_VERSION};
+use state::{PersistedDigest, PersistedState, PersistedUrl, STATE_VERSION};
+use token_file::TokenFile;
+use wasm_check::{WasmCheck, WasmCheckConfig, WasmInput, WasmStatus, WasmVerdict, INTERFACE_VERSION};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::env;
-use std::time::Duration;
-use tokio::time::{interval};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{interval_at, sleep, timeout, Instant, Interval, MissedTickBehavior};
 use url::Url;
+use uuid::Uuid;
 
 #[derive(Debug, Clone, PartialEq)]
 enum UrlStatus {
     Up,
+    /// Responding, but slower than the URL's configured latency threshold.
+    Degraded,
+    /// Answering with 429 Too Many Requests.
+    Throttled,
     Down,
 }
 
@@ -17,237 +84,6244 @@ impl std::fmt::Display for UrlStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             UrlStatus::Up => write!(f, "UP"),
+            UrlStatus::Degraded => write!(f, "DEGRADED"),
+            UrlStatus::Throttled => write!(f, "THROTTLED"),
             UrlStatus::Down => write!(f, "DOWN"),
         }
     }
 }
 
-struct Config {
-    urls: Vec<String>,
-    slack_webhook: String,
-    interval_seconds: u64,
-    test_mode: bool,
+impl UrlStatus {
+    fn emoji(&self) -> &'static str {
+        match self {
+            UrlStatus::Up => "✅",
+            UrlStatus::Degraded => "⚠️",
+            UrlStatus::Throttled => "🚦",
+            UrlStatus::Down => "❌",
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match self {
+            UrlStatus::Up => Severity::Info,
+            UrlStatus::Degraded | UrlStatus::Throttled => Severity::Warning,
+            UrlStatus::Down => Severity::Critical,
+        }
+    }
+
+    /// Inverse of `Display`, used when restoring persisted state.
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "UP" => Some(UrlStatus::Up),
+            "DEGRADED" => Some(UrlStatus::Degraded),
+            "THROTTLED" => Some(UrlStatus::Throttled),
+            "DOWN" => Some(UrlStatus::Down),
+            _ => None,
+        }
+    }
+
+    /// Degraded and throttled URLs still respond, so they count as available.
+    fn is_available(&self) -> bool {
+        *self != UrlStatus::Down
+    }
 }
 
-impl Config {
-    fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
-        let urls_str = env::var("UPNOTIF_URLS")
-            .map_err(|_| "UPNOTIF_URLS environment variable is required")?;
+/// Outcome of a single check, with an optional explanation for failures.
+#[derive(Debug, Clone)]
+struct CheckResult {
+    status: UrlStatus,
+    reason: Option<String>,
+    latency: Option<Duration>,
+    /// How long the server asked us to wait before checking again.
+    retry_after: Option<Duration>,
+    /// HTTP status of the response, when one was received.
+    status_code: Option<u16>,
+    /// Cache validators of a successful full response, for URLs watched for changes.
+    validators: Option<Validators>,
+    /// Hash of the response body, for URLs checked for a stuck backend.
+    body_hash: Option<u64>,
+    /// Address of the server that sent the HTTP response.
+    remote_addr: Option<SocketAddr>,
+    /// Kind of failure of a request that got no HTTP response.
+    error_category: Option<FailureCategory>,
+    /// Time until the response headers arrived, for URLs that measure it.
+    ttfb: Option<Duration>,
+}
 
-        let slack_webhook = env::var("UPNOTIF_SLACK_WEBHOOK")
-            .map_err(|_| "UPNOTIF_SLACK_WEBHOOK environment variable is required")?;
+/// Broad kind of a failure, used to tell when an ongoing outage changes its nature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FailureCategory {
+    Timeout,
+    Dns,
+    Connection,
+    Tls,
+    Http(u16),
+    /// A successful response that failed a header or body check.
+    Content,
+    Other,
+}
 
-        let interval_seconds = env::var("UPNOTIF_INTERVAL_SECONDS")
-            .unwrap_or_else(|_| "60".to_string())
-            .parse::<u64>()
-            .map_err(|_| "UPNOTIF_INTERVAL_SECONDS must be a valid number")?;
+impl std::fmt::Display for FailureCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailureCategory::Timeout => write!(f, "timeout"),
+            FailureCategory::Dns => write!(f, "DNS failure"),
+            FailureCategory::Connection => write!(f, "connection failure"),
+            FailureCategory::Tls => write!(f, "TLS error"),
+            FailureCategory::Http(code) => write!(f, "HTTP {}", code),
+            FailureCategory::Content => write!(f, "content check failure"),
+            FailureCategory::Other => write!(f, "other error"),
+        }
+    }
+}
 
-        let urls: Vec<String> = urls_str
-            .split(',')
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
+/// `ETag` and `Last-Modified` of a response, sent back as conditional request headers.
+#[derive(Debug, Clone, PartialEq)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
 
-        if urls.is_empty() {
-            return Err("At least one URL must be provided in UPNOTIF_URLS".into());
+impl Validators {
+    fn from_response(response: &reqwest::Response) -> Option<Self> {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let validators = Self {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        };
+        (validators.etag.is_some() || validators.last_modified.is_some()).then_some(validators)
+    }
+
+    fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
         }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+}
 
-        // Validate URLs
-        for url in &urls {
-            Url::parse(url)
-                .map_err(|_| format!("Invalid URL: {}", url))?;
+impl CheckResult {
+    fn up() -> Self {
+        Self {
+            status: UrlStatus::Up,
+            reason: None,
+            latency: None,
+            retry_after: None,
+            status_code: None,
+            validators: None,
+            body_hash: None,
+            remote_addr: None,
+            error_category: None,
+            ttfb: None,
         }
+    }
 
-        let test_mode = slack_webhook == "test";
+    fn down(reason: Option<String>) -> Self {
+        Self {
+            status: UrlStatus::Down,
+            reason,
+            latency: None,
+            retry_after: None,
+            status_code: None,
+            validators: None,
+            body_hash: None,
+            remote_addr: None,
+            error_category: None,
+            ttfb: None,
+        }
+    }
 
-        // Validate Slack webhook URL (unless in test mode)
-        if !test_mode {
-            Url::parse(&slack_webhook)
-                .map_err(|_| "Invalid Slack webhook URL")?;
+    /// Result for a `429 Too Many Requests` response, mapped according to configuration.
+    fn rate_limited(mode: ThrottledStatus, retry_after: Option<Duration>) -> Self {
+        let reason = match retry_after {
+            Some(wait) => format!("rate limited, retry after {}s", wait.as_secs()),
+            None => "rate limited".to_string(),
+        };
+        let result = match mode {
+            ThrottledStatus::Throttled => Self {
+                status: UrlStatus::Throttled,
+                ..Self::down(Some(reason))
+            },
+            ThrottledStatus::Up => Self::up(),
+            ThrottledStatus::Down => Self::down(Some(reason)),
+        };
+        Self { retry_after, ..result }
+    }
+
+    fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    fn failure_category(&self) -> FailureCategory {
+        if let Some(category) = self.error_category {
+            return category;
         }
+        match self.status_code {
+            Some(code) if !(200..300).contains(&code) => FailureCategory::Http(code),
+            Some(_) => FailureCategory::Content,
+            None => FailureCategory::Other,
+        }
+    }
 
-        Ok(Config {
-            urls,
-            slack_webhook,
-            interval_seconds,
-            test_mode,
-        })
+    /// Appends the request's correlation ID to the reason of a failed check.
+    fn with_request_id(mut self, request_id: Option<&str>) -> Self {
+        if let (UrlStatus::Down, Some(id)) = (&self.status, request_id) {
+            self.reason = Some(match self.reason.take() {
+                Some(reason) => format!("{}, request id {}", reason, id),
+                None => format!("request id {}", id),
+            });
+        }
+        self
+    }
+
+    /// Degrades an UP result with a warning about a check that passed otherwise.
+    fn with_warning(self, warning: Option<String>) -> Self {
+        match warning {
+            Some(warning) if self.status == UrlStatus::Up => Self {
+                status: UrlStatus::Degraded,
+                reason: Some(warning),
+                ..self
+            },
+            _ => self,
+        }
     }
 }
 
-struct UrlMonitor {
-    client: Client,
-    config: Config,
-    status_map: HashMap<String, UrlStatus>,
+/// A single line of a status change notification.
+#[derive(Debug, Clone)]
+struct Change {
+    line: String,
+    severity: Severity,
+    mentions: Vec<Mention>,
+    /// Tags of the URL the change is about, used to pick notification routes.
+    tags: BTreeMap<String, String>,
+    /// Set for changes of a URL's status, which PagerDuty routes track as incidents.
+    incident: Option<Incident>,
 }
 
-impl UrlMonitor {
-    fn new(config: Config) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .expect("Failed to create HTTP client");
+/// Where a message is sent: the default Slack webhook or one of the configured routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Destination {
+    Default,
+    Route(usize),
+}
 
-        Self {
-            client,
-            config,
-            status_map: HashMap::new(),
+/// Everything tracked about a URL across check cycles.
+#[derive(Debug, Clone)]
+struct UrlState {
+    status: UrlStatus,
+    reason: Option<String>,
+    latency: Option<Duration>,
+    last_checked: u64,
+    checks: u64,
+    up_checks: u64,
+    latency_baseline: LatencyBaseline,
+    /// Failed checks in a row, including ones not yet confirmed by the failure threshold.
+    consecutive_failures: u32,
+    /// When the current run of failed checks started.
+    failing_since: Option<u64>,
+    /// When a DOWN URL started passing again, while its recovery waits for the recovery hold.
+    recovering_since: Option<u64>,
+    /// Whether the last check was a latency anomaly, so an ongoing one is only reported once.
+    latency_anomalous: bool,
+    /// Consecutive checks answered with one of `UPNOTIF_GONE_STATUS_CODES`.
+    gone_checks: u32,
+    /// Set once the URL is no longer checked because it appears to be gone for good.
+    auto_disabled: bool,
+    /// Validators of the last full response of a URL watched for changes.
+    validators: Option<Validators>,
+    /// Hash of the last response body and how many checks in a row returned it.
+    last_body_hash: Option<u64>,
+    identical_bodies: u32,
+    /// Category of the current outage's failure as last reported.
+    failure_category: Option<FailureCategory>,
+    /// The DOWN dependency whose alert covered this URL's outage, so its recovery isn't alerted either.
+    grouped_under: Option<String>,
+}
+
+impl UrlState {
+    fn uptime_percent(&self) -> f64 {
+        if self.checks == 0 {
+            return 0.0;
         }
+        self.up_checks as f64 * 100.0 / self.checks as f64
     }
+}
 
-    async fn check_url_status(&self, url: &str) -> UrlStatus {
-        match self.client.get(url).send().await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    UrlStatus::Up
-                } else {
-                    UrlStatus::Down
-                }
-            }
-            Err(_) => UrlStatus::Down,
+/// Running mean and variance of a URL's response time in milliseconds (Welford's algorithm).
+#[derive(Debug, Clone, Default)]
+struct LatencyBaseline {
+    samples: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl LatencyBaseline {
+    fn add(&mut self, latency_ms: f64) {
+        self.samples += 1;
+        let delta = latency_ms - self.mean;
+        self.mean += delta / self.samples as f64;
+        self.m2 += delta * (latency_ms - self.mean);
+    }
+
+    fn std_dev(&self) -> f64 {
+        if self.samples < 2 {
+            return 0.0;
         }
+        (self.m2 / (self.samples - 1) as f64).sqrt()
     }
 
-    async fn send_notification(&self, message: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if self.config.test_mode {
-            // In test mode, just log to console
-            info!("[TEST MODE] Slack notification: {}", message);
-            Ok(())
-        } else {
-            // Send actual Slack notification
-            let payload = json!({
-                "text": message
-            });
+    /// Standard deviation relative to the mean, which compares the spread of fast and slow endpoints.
+    fn coefficient_of_variation(&self) -> f64 {
+        self.std_dev() / self.mean.max(f64::EPSILON)
+    }
+
+    /// How many standard deviations a sample lies above the mean.
+    ///
+    /// The deviation is floored at 5% of the mean so that very steady endpoints don't
+    /// turn a millisecond of jitter into a many-sigma outlier.
+    fn deviation(&self, latency_ms: f64) -> f64 {
+        let std_dev = self.std_dev().max(self.mean * 0.05).max(f64::EPSILON);
+        (latency_ms - self.mean) / std_dev
+    }
+}
 
-            let response = self
-                .client
-                .post(&self.config.slack_webhook)
-                .json(&payload)
-                .send()
-                .await?;
+/// A response time far outside a URL's learned baseline.
+struct LatencyAnomaly {
+    latency_ms: f64,
+    mean_ms: f64,
+    deviation: f64,
+}
 
-            if !response.status().is_success() {
-                return Err(format!("Slack webhook returned status: {}", response.status()).into());
-            }
+/// Result of checking one URL in a cycle, compared against its previous status.
+struct UrlCheck {
+    url: String,
+    name: Option<String>,
+    tags: BTreeMap<String, String>,
+    result: CheckResult,
+    previous: Option<UrlStatus>,
+    latency_anomaly: Option<LatencyAnomaly>,
+    mentions: Vec<Mention>,
+    /// When the failures before this check started, if the previous check failed.
+    down_since: Option<u64>,
+    /// Status code that kept coming back until this check disabled the URL.
+    auto_disabled: Option<u16>,
+    /// Whether a URL watched for changes returned different cache validators than last time.
+    content_changed: bool,
+    /// Previous failure category of a URL that stayed DOWN but now fails differently.
+    failure_changed: Option<FailureCategory>,
+    /// Whether DOWN is the URL's normal state, as for a decommissioned service.
+    expect_down: bool,
+}
+
+impl UrlCheck {
+    fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.url)
+    }
+
+    fn status_changed(&self) -> bool {
+        self.previous.as_ref() != Some(&self.result.status)
+    }
 
-            Ok(())
+    fn incident(&self) -> Incident {
+        Incident {
+            key: self.url.clone(),
+            resolved: self.result.status == UrlStatus::Up,
         }
     }
 
-    async fn check_all_urls(&mut self) -> Vec<(String, UrlStatus, bool)> {
-        let mut results = Vec::new();
+    /// Renders tags as ` [key:value, ...]`, or an empty string when there are none.
+    fn tag_suffix(&self) -> String {
+        if self.tags.is_empty() {
+            return String::new();
+        }
 
-        for url in &self.config.urls {
-            let current_status = self.check_url_status(url).await;
-            let previous_status = self.status_map.get(url);
-            let status_changed = previous_status.map_or(true, |prev| prev != &current_status);
+        let tags: Vec<String> = self
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{}:{}", key, value))
+            .collect();
+        format!(" [{}]", tags.join(", "))
+    }
+}
 
-            results.push((url.clone(), current_status.clone(), status_changed));
-            self.status_map.insert(url.clone(), current_status);
+impl std::fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "{} ({})", self.status, reason),
+            None => write!(f, "{}", self.status),
         }
+    }
+}
 
-        results
+/// Extra time warmed connections stay pooled beyond the warmup interval.
+const WARMUP_IDLE_MARGIN: Duration = Duration::from_secs(30);
+
+/// Smallest accepted `UPNOTIF_MAX_MESSAGE_LENGTH`, leaving room for at least one status line.
+const MIN_MESSAGE_LEN: usize = 100;
+
+/// Upper bound on a `Retry-After` backoff so a bogus header can't silence a URL for days.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(3600);
+
+/// Same limit as reqwest's default redirect policy.
+const MAX_REDIRECTS: usize = 10;
+
+/// URL schemes with a check implementation; anything else is rejected at startup.
+const SUPPORTED_SCHEMES: &[&str] = &["http", "https", "grpc", "grpcs"];
+
+/// Settings for alerting on response times far above a URL's learned baseline.
+#[derive(Debug, Clone)]
+struct AnomalyDetection {
+    /// Standard deviations above the mean that count as an anomaly.
+    sigma: f64,
+    /// Samples collected before a URL's baseline is trusted.
+    warmup: u64,
+}
+
+/// How a `429 Too Many Requests` response is reported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ThrottledStatus {
+    Throttled,
+    Up,
+    Down,
+}
+
+impl ThrottledStatus {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "throttled" => Some(ThrottledStatus::Throttled),
+            "up" => Some(ThrottledStatus::Up),
+            "down" => Some(ThrottledStatus::Down),
+            _ => None,
+        }
     }
+}
 
-    async fn report_initial_status(&mut self) {
-        info!("🚀 Starting URL monitoring...");
+/// How a `204 No Content` response is reported for a URL with body checks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NoContentStatus {
+    /// Successful like any 2xx; the body checks are skipped.
+    Up,
+    /// Failed, since there is no body that could pass the checks.
+    Fail,
+}
 
-        let results = self.check_all_urls().await;
-        let mut status_lines = Vec::new();
+impl NoContentStatus {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "up" => Some(NoContentStatus::Up),
+            "fail" => Some(NoContentStatus::Fail),
+            _ => None,
+        }
+    }
+}
 
-        for (url, status, _) in results {
-            let emoji = match status {
-                UrlStatus::Up => "✅",
-                UrlStatus::Down => "❌",
-            };
-            let line = format!("{} {} is {}", emoji, url, status);
-            info!("{}", line);
-            status_lines.push(line);
+/// How much each status line of a notification includes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DetailLevel {
+    /// Just the name and status, e.g. "Prod API DOWN".
+    Terse,
+    Normal,
+    /// Adds latency, status code and check time.
+    Verbose,
+}
+
+impl DetailLevel {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "terse" => Some(DetailLevel::Terse),
+            "normal" => Some(DetailLevel::Normal),
+            "verbose" => Some(DetailLevel::Verbose),
+            _ => None,
         }
+    }
+}
 
-        let message = format!(
-            "🔍 *URL Monitor Started*\nInitial status check:\n{}",
-            status_lines.join("\n")
-        );
+/// What to do when the startup test notification cannot be delivered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NotifierCheck {
+    /// No test notification is sent.
+    Off,
+    /// Log an error and keep monitoring.
+    Warn,
+    /// Exit so a misconfigured webhook is noticed right away.
+    Exit,
+}
 
-        if let Err(e) = self.send_notification(&message).await {
-            if self.config.test_mode {
-                error!("Failed to log initial status: {}", e);
-            } else {
-                error!("Failed to send initial status to Slack: {}", e);
-            }
+impl NotifierCheck {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Some(NotifierCheck::Off),
+            "warn" => Some(NotifierCheck::Warn),
+            "exit" => Some(NotifierCheck::Exit),
+            _ => None,
         }
     }
+}
 
-    async fn monitor_urls(&mut self) {
-        let mut interval_timer = interval(Duration::from_secs(self.config.interval_seconds));
-        interval_timer.tick().await; // Skip the first tick
+/// How the monitor announces itself when it starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StartupMode {
+    /// Check everything and send the startup report.
+    Report,
+    /// Take over from a previous instance through the state file: no report, and the first
+    /// cycle alerts only on changes from the saved statuses.
+    Handoff,
+}
 
-        loop {
-            interval_timer.tick().await;
+impl StartupMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "report" => Some(StartupMode::Report),
+            "handoff" => Some(StartupMode::Handoff),
+            _ => None,
+        }
+    }
+}
 
-            let results = self.check_all_urls().await;
-            let mut changes = Vec::new();
+/// What the schedule does after a check cycle took longer than the interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OverrunPolicy {
+    /// Drop the missed cycles and wait for the next one on the regular schedule.
+    Skip,
+    /// Start the next cycle right away, stretching the interval to the time checks take.
+    Extend,
+}
 
-            for (url, status, status_changed) in results {
-                if status_changed {
-                    let emoji = match status {
-                        UrlStatus::Up => "✅",
-                        UrlStatus::Down => "❌",
-                    };
-                    let change_msg = format!("{} {} is now {}", emoji, url, status);
-                    info!("Status change: {}", change_msg);
-                    changes.push(change_msg);
-                }
-            }
+impl OverrunPolicy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "skip" => Some(OverrunPolicy::Skip),
+            "extend" => Some(OverrunPolicy::Extend),
+            _ => None,
+        }
+    }
+}
 
-            if !changes.is_empty() {
-                let message = format!(
-                    "🔔 *URL Status Changes*\n{}",
-                    changes.join("\n")
-                );
+/// A single monitored URL together with its optional per-URL checks.
+#[derive(Debug, Clone)]
+struct UrlSpec {
+    url: String,
+    name: Option<String>,
+    tags: BTreeMap<String, String>,
+    json_schema: Option<jsonschema::Validator>,
+    readiness: Option<ReadinessCheck>,
+    /// Another URL this one's response times are compared with.
+    compare_latency: Option<LatencyComparison>,
+    /// Limit on how much this URL's recent response times may vary.
+    latency_consistency: Option<LatencyConsistency>,
+    paths: Vec<PathCheck>,
+    /// Feature flag that decides whether the URL is checked, when `UPNOTIF_FLAG_URL` is set.
+    flag: Option<String>,
+    /// URLs this one needs; its DOWN alerts are grouped under theirs while they are DOWN.
+    depends_on: Vec<String>,
+    /// A decommissioned service: DOWN is its normal state, and coming back UP is what alerts.
+    expect_down: bool,
+    /// Regional endpoints checked in place of `url`, by region name.
+    regions: Vec<(String, Url)>,
+    /// What the responses of all regions must agree on.
+    region_compare: Option<RegionCompare>,
+    method: Method,
+    body: Option<String>,
+    /// `body` gzip-compressed, sent in its place when `compress_body` is set.
+    compressed_body: Option<bytes::Bytes>,
+    retry_non_idempotent: bool,
+    degraded_latency: Option<Duration>,
+    /// Slower successful checks count as failures, reporting the URL DOWN.
+    down_latency: Option<Duration>,
+    /// Send requests as HTTP/1.0 with `Connection: close`, for legacy servers that mishandle HTTP/1.1.
+    http_1_0: bool,
+    /// Record the time to first byte and read the whole body, so `latency` includes the transfer.
+    measure_ttfb: bool,
+    degraded_ttfb: Option<Duration>,
+    track_dns: bool,
+    /// SHA-256 fingerprints of acceptable leaf certificates; empty disables pinning.
+    cert_fingerprints: Vec<[u8; 32]>,
+    /// Report the URL DEGRADED when its handshake lacks a good stapled OCSP response.
+    check_ocsp_stapling: bool,
+    /// Hosts other than the URL's own that redirects may end on.
+    allowed_redirect_hosts: Vec<String>,
+    /// Where the URL must redirect to; checked without following redirects.
+    expect_redirect: Option<Url>,
+    /// Acceptable status codes of that redirect; any 3xx when unset.
+    redirect_status: Option<StatusCodes>,
+    /// Also probe the URL's TCP port to tell network failures from application errors.
+    tcp_check: bool,
+    /// Send conditional requests and track the response's cache validators.
+    watch_changes: bool,
+    /// Notify when the validators of a watched URL change.
+    alert_on_change: bool,
+    /// Whether HTTP checks go through `UPNOTIF_SOCKS_PROXY` when one is configured.
+    socks_proxy: bool,
+    /// Who to ping when this URL goes down.
+    mentions: Vec<Mention>,
+    /// Checks per cycle, combined according to `sample_policy`.
+    samples: u32,
+    sample_policy: SamplePolicy,
+    /// Signs check requests for endpoints that require it.
+    signer: Option<RequestSigner>,
+    /// Fetches the bearer token for URLs behind OAuth2 client credentials.
+    oauth: Option<Arc<TokenProvider>>,
+    /// File holding a bearer token that is rotated outside the monitor.
+    bearer_token_file: Option<Arc<TokenFile>>,
+    /// The response body must parse as JSON, whatever it contains.
+    expect_json: bool,
+    /// Numeric conditions on fields of the JSON response.
+    assertions: Vec<Assertion>,
+    assertion_status: AssertionStatus,
+    /// Strings the response body must include.
+    must_contain: Vec<String>,
+    /// Error markers the response body must not include.
+    must_not_contain: Vec<String>,
+    /// `Accept`, `Accept-Language` and `Accept-Encoding` values sent with checks, and the
+    /// `Cache-Control` and `Pragma` headers of `no_cache`.
+    negotiation_headers: Vec<(HeaderName, HeaderValue)>,
+    /// Query parameter set to a random value on every check, so no cache can answer it.
+    cache_bust_param: Option<String>,
+    /// Smallest acceptable response body, to catch truncated responses with a 2xx status.
+    min_body_bytes: Option<usize>,
+    /// Resolves the URL's host through this server instead of `UPNOTIF_DNS_SERVER`.
+    dns_server: Option<SocketAddr>,
+    /// Report the URL degraded once this many checks in a row returned an identical body.
+    stuck_after: Option<u32>,
+    /// Patterns that a response header must match, in config order.
+    expect_headers: Vec<(HeaderName, Regex)>,
+    /// Cookies the response must set; a cookie without its expected attributes degrades the URL.
+    expect_cookies: Vec<ExpectedCookie>,
+    /// Module that decides the URL's status from the response, instead of the built-in checks.
+    wasm_check: Option<Arc<WasmCheck>>,
+}
 
-                if let Err(e) = self.send_notification(&message).await {
-                    if self.config.test_mode {
-                        error!("Failed to log status change: {}", e);
-                    } else {
-                        error!("Failed to send status change to Slack: {}", e);
-                    }
-                }
-            }
+/// One path of a multi-path service, checked against the service's base URL.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PathCheck {
+    path: String,
+    /// Expected status code(s); any 2xx when unset.
+    #[serde(default)]
+    expect_status: Option<StatusCodes>,
+}
+
+/// A cookie the response must set, and the security attributes it should carry.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExpectedCookie {
+    name: String,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    http_only: bool,
+    /// `Strict`, `Lax` or `None`, compared case-insensitively.
+    #[serde(default)]
+    same_site: Option<String>,
+}
+
+/// The part of a regional response that must be the same in every region.
+#[derive(Debug, Clone)]
+enum RegionCompare {
+    Json(JsonPath),
+    Header(HeaderName),
+}
+
+impl RegionCompare {
+    /// `$.version` selects a field of a JSON body, anything else names a response header.
+    fn parse(value: &str) -> Option<Self> {
+        if value.starts_with('$') {
+            JsonPath::parse(value).map(RegionCompare::Json)
+        } else {
+            HeaderName::from_bytes(value.as_bytes()).ok().map(RegionCompare::Header)
         }
     }
+}
 
-    async fn run(&mut self) {
-        self.report_initial_status().await;
+impl std::fmt::Display for RegionCompare {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegionCompare::Json(path) => write!(f, "{}", path),
+            RegionCompare::Header(name) => write!(f, "header {}", name),
+        }
+    }
+}
 
-        info!(
-            "Monitoring {} URLs every {} seconds...",
-            self.config.urls.len(),
-            self.config.interval_seconds
-        );
+/// How the samples of a URL checked several times per cycle are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SamplePolicy {
+    /// More than half of the samples must succeed.
+    #[default]
+    Majority,
+    /// Every sample must succeed.
+    All,
+    /// At least one sample must succeed.
+    Any,
+}
 
-        self.monitor_urls().await;
+impl SamplePolicy {
+    fn passes(&self, succeeded: u32, samples: u32) -> bool {
+        match self {
+            SamplePolicy::Majority => succeeded * 2 > samples,
+            SamplePolicy::All => succeeded == samples,
+            SamplePolicy::Any => succeeded > 0,
+        }
     }
 }
 
-#[tokio::main]
-async fn main() {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+/// Status reported when a JSON assertion of a URL fails.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AssertionStatus {
+    #[default]
+    Down,
+    Degraded,
+}
 
-    let config = match Config::from_env() {
-        Ok(config) => config,
-        Err(e) => {
-            error!("Configuration error: {}", e);
-            std::process::exit(1);
+/// One status code or a list of acceptable codes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum StatusCodes {
+    One(u16),
+    Many(Vec<u16>),
+}
+
+impl StatusCodes {
+    fn codes(&self) -> &[u16] {
+        match self {
+            StatusCodes::One(code) => std::slice::from_ref(code),
+            StatusCodes::Many(codes) => codes,
         }
-    };
+    }
 
-    info!("Configuration loaded successfully");
-    info!("URLs to monitor: {:?}", config.urls);
-    info!("Check interval: {} seconds", config.interval_seconds);
+    fn contains(&self, code: u16) -> bool {
+        self.codes().contains(&code)
+    }
+
+    fn includes_redirect(&self) -> bool {
+        self.codes().iter().any(|code| (300..400).contains(code))
+    }
+}
+
+impl std::fmt::Display for StatusCodes {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let codes: Vec<String> = self.codes().iter().map(u16::to_string).collect();
+        write!(f, "{}", codes.join(" or "))
+    }
+}
+
+/// A heavier secondary endpoint verified on a coarser schedule than its primary URL.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadinessCheck {
+    url: String,
+    /// Check the readiness endpoint every this many cycles.
+    #[serde(default = "default_readiness_every")]
+    every: u64,
+    /// Also check it whenever the primary check is slower than this.
+    #[serde(default)]
+    when_slower_than_ms: Option<u64>,
+}
+
+fn default_readiness_every() -> u64 {
+    10
+}
+
+/// Warns when a URL becomes much slower than a reference URL, e.g. a new version of an endpoint
+/// compared with the old one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LatencyComparison {
+    /// URL or name of the reference URL.
+    with: String,
+    /// How much slower than the reference, in percent, the average response time may be.
+    max_slower_percent: f64,
+    /// Successful checks of each URL the averages are taken over.
+    #[serde(default = "default_latency_window")]
+    window: usize,
+}
+
+fn default_latency_window() -> usize {
+    10
+}
+
+/// Warns when a URL's response times become erratic, even if their average is fine.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LatencyConsistency {
+    /// Highest accepted coefficient of variation, the standard deviation divided by the mean.
+    max_cv: f64,
+    /// Successful checks the variation is measured over, and collected before it is trusted.
+    #[serde(default = "default_consistency_window")]
+    window: usize,
+}
+
+fn default_consistency_window() -> usize {
+    30
+}
+
+impl ReadinessCheck {
+    fn is_due(&self, cycle: u64, primary: &CheckResult) -> bool {
+        if !primary.status.is_available() {
+            return false;
+        }
+
+        let slow = match (self.when_slower_than_ms, primary.latency) {
+            (Some(limit), Some(latency)) => latency > Duration::from_millis(limit),
+            _ => false,
+        };
+
+        slow || cycle % self.every == 0
+    }
+}
+
+impl UrlSpec {
+    /// Retrying may repeat side effects, so only GET and HEAD are retried unless opted in.
+    fn retryable(&self) -> bool {
+        self.retry_non_idempotent || self.method == Method::GET || self.method == Method::HEAD
+    }
+
+    /// Compares an unfollowed response with `expect_redirect` and `redirect_status`.
+    fn check_redirect(&self, response: &reqwest::Response) -> Result<(), String> {
+        let Some(expected) = &self.expect_redirect else {
+            return Ok(());
+        };
+        let code = response.status().as_u16();
+        if !response.status().is_redirection() {
+            return Err(format!("returned {} instead of redirecting to {}", code, expected));
+        }
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| format!("returned {} without a Location header, expected a redirect to {}", code, expected))?;
+        // Relative locations are resolved the way a client following the redirect would
+        let target = response
+            .url()
+            .join(location)
+            .map_err(|_| format!("redirected to invalid location '{}' instead of {}", location, expected))?;
+        if target != *expected {
+            return Err(format!("redirected to {} instead of {}", target, expected));
+        }
+        match &self.redirect_status {
+            Some(codes) if !codes.contains(code) => Err(format!("redirected with {} instead of {}", code, codes)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The host a response ended up on if redirects took it somewhere not allowed for this URL.
+    fn unexpected_redirect_host(&self, response: &reqwest::Response) -> Option<String> {
+        let final_host = response.url().host_str()?.to_lowercase();
+        if final_host == url_host(&self.url) || self.allowed_redirect_hosts.contains(&final_host) {
+            None
+        } else {
+            Some(final_host)
+        }
+    }
+
+    /// Every URL a check of this spec sends requests to.
+    fn request_urls(&self) -> Vec<&str> {
+        let mut urls = if self.regions.is_empty() {
+            vec![self.url.as_str()]
+        } else {
+            self.regions.iter().map(|(_, url)| url.as_str()).collect()
+        };
+        urls.extend(self.readiness.as_ref().map(|readiness| readiness.url.as_str()));
+        urls
+    }
+
+    /// Adds the OAuth2 or token file bearer token, if the URL uses one.
+    async fn authorize(&self, client: &Client, request: RequestBuilder) -> Result<RequestBuilder, String> {
+        match (&self.oauth, &self.bearer_token_file) {
+            (Some(oauth), _) => oauth
+                .authorize(client, request)
+                .await
+                .map_err(|e| format!("failed to get OAuth2 token: {}", e)),
+            (None, Some(token_file)) => token_file.authorize(request),
+            (None, None) => Ok(request),
+        }
+    }
+
+    /// Drops a cached token the endpoint rejected, so the next check fetches a fresh one.
+    async fn note_status(&self, status: StatusCode) {
+        if status != StatusCode::UNAUTHORIZED {
+            return;
+        }
+        if let Some(oauth) = &self.oauth {
+            oauth.invalidate().await;
+        }
+        if let Some(token_file) = &self.bearer_token_file {
+            token_file.invalidate();
+        }
+    }
+
+    /// Adds the configured content negotiation headers to a check request.
+    fn apply_negotiation_headers(&self, mut request: RequestBuilder) -> RequestBuilder {
+        for (header, value) in &self.negotiation_headers {
+            request = request.header(header.clone(), value.clone());
+        }
+        request
+    }
+
+    /// Adds a random `cache_bust_param` to a check URL, if the URL sets one.
+    fn cache_busted(&self, mut url: Url) -> Url {
+        if let Some(param) = &self.cache_bust_param {
+            url.query_pairs_mut().append_pair(param, &format!("{:016x}", rand::random::<u64>()));
+        }
+        url
+    }
+
+    /// Downgrades the request to HTTP/1.0 without keep-alive for URLs with `http_1_0`.
+    fn apply_http_version(&self, request: RequestBuilder) -> RequestBuilder {
+        if !self.http_1_0 {
+            return request;
+        }
+        request
+            .version(reqwest::Version::HTTP_10)
+            .header(reqwest::header::CONNECTION, "close")
+    }
+
+    /// Adds the configured body, gzip-compressed with a matching `Content-Encoding` when `compress_body` is set.
+    fn apply_body(&self, request: RequestBuilder) -> RequestBuilder {
+        match (&self.compressed_body, &self.body) {
+            (Some(compressed), _) => request
+                .header(reqwest::header::CONTENT_ENCODING, "gzip")
+                .body(compressed.clone()),
+            (None, Some(body)) => request.body(body.clone()),
+            (None, None) => request,
+        }
+    }
+
+    /// Marks a successful check as DOWN or DEGRADED when it was slower than the configured limits.
+    fn apply_latency_threshold(&self, result: CheckResult) -> CheckResult {
+        if let (Some(limit), Some(latency)) = (self.down_latency, result.latency) {
+            if result.status.is_available() && latency > limit {
+                return CheckResult {
+                    status: UrlStatus::Down,
+                    reason: Some(format!(
+                        "response too slow: {}ms, limit {}ms",
+                        latency.as_millis(),
+                        limit.as_millis()
+                    )),
+                    error_category: Some(FailureCategory::Timeout),
+                    ..result
+                };
+            }
+        }
+
+        match (self.degraded_latency, result.latency, self.degraded_ttfb, result.ttfb) {
+            (Some(limit), Some(latency), _, _) if result.status == UrlStatus::Up && latency > limit => CheckResult {
+                status: UrlStatus::Degraded,
+                reason: Some(format!(
+                    "slow response: {}ms, threshold {}ms",
+                    latency.as_millis(),
+                    limit.as_millis()
+                )),
+                ..result
+            },
+            (_, _, Some(limit), Some(ttfb)) if result.status == UrlStatus::Up && ttfb > limit => CheckResult {
+                status: UrlStatus::Degraded,
+                reason: Some(format!(
+                    "slow first byte: {}ms, threshold {}ms",
+                    ttfb.as_millis(),
+                    limit.as_millis()
+                )),
+                ..result
+            },
+            _ => result,
+        }
+    }
+
+    fn plain(url: String) -> Self {
+        Self {
+            url,
+            name: None,
+            tags: BTreeMap::new(),
+            json_schema: None,
+            readiness: None,
+            compare_latency: None,
+            latency_consistency: None,
+            paths: Vec::new(),
+            flag: None,
+            depends_on: Vec::new(),
+            expect_down: false,
+            regions: Vec::new(),
+            region_compare: None,
+            method: Method::GET,
+            body: None,
+            compressed_body: None,
+            retry_non_idempotent: false,
+            degraded_latency: None,
+            down_latency: None,
+            http_1_0: false,
+            measure_ttfb: false,
+            degraded_ttfb: None,
+            track_dns: false,
+            cert_fingerprints: Vec::new(),
+            check_ocsp_stapling: false,
+            allowed_redirect_hosts: Vec::new(),
+            expect_redirect: None,
+            redirect_status: None,
+            tcp_check: false,
+            watch_changes: false,
+            alert_on_change: false,
+            socks_proxy: true,
+            mentions: Vec::new(),
+            samples: 1,
+            sample_policy: SamplePolicy::default(),
+            signer: None,
+            oauth: None,
+            bearer_token_file: None,
+            expect_json: false,
+            assertions: Vec::new(),
+            assertion_status: AssertionStatus::Down,
+            must_contain: Vec::new(),
+            must_not_contain: Vec::new(),
+            negotiation_headers: Vec::new(),
+            cache_bust_param: None,
+            min_body_bytes: None,
+            dns_server: None,
+            stuck_after: None,
+            expect_headers: Vec::new(),
+            expect_cookies: Vec::new(),
+            wasm_check: None,
+        }
+    }
+
+    fn from_entry(entry: UrlEntry, base_dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        for (key, value) in &entry.tags {
+            if !is_label_safe(key) {
+                return Err(format!(
+                    "Invalid tag key '{}' for {}: use letters, digits and underscores, not starting with a digit",
+                    key, entry.url
+                )
+                .into());
+            }
+            if value.is_empty() {
+                return Err(format!("Tag '{}' for {} must have a value", key, entry.url).into());
+            }
+        }
+
+        if let Some(readiness) = &entry.readiness {
+            Url::parse(&readiness.url)
+                .map_err(|_| format!("Invalid readiness URL for {}: {}", entry.url, readiness.url))?;
+            if !is_http_url(&readiness.url) {
+                return Err(format!("Readiness URL for {} must be http:// or https://: {}", entry.url, readiness.url).into());
+            }
+            if readiness.every == 0 {
+                return Err(format!("Readiness 'every' for {} must be a positive number", entry.url).into());
+            }
+        }
+
+        // Service paths and regions only check status codes, so options for the single URL's response don't apply
+        let response_options = [
+            ("json_schema", entry.json_schema.is_some()),
+            ("expect_json", entry.expect_json),
+            ("assertions", !entry.assertions.is_empty()),
+            ("must_contain", !entry.must_contain.is_empty()),
+            ("must_not_contain", !entry.must_not_contain.is_empty()),
+            ("min_body_bytes", entry.min_body_bytes.is_some()),
+            ("stuck_after", entry.stuck_after.is_some()),
+            ("expect_headers", !entry.expect_headers.is_empty()),
+            ("expect_cookies", !entry.expect_cookies.is_empty()),
+            ("wasm_check", entry.wasm_check.is_some()),
+            ("readiness", entry.readiness.is_some()),
+            ("expect_redirect", entry.expect_redirect.is_some()),
+            ("measure_ttfb", entry.measure_ttfb),
+            ("degraded_ttfb_ms", entry.degraded_ttfb_ms.is_some()),
+        ];
+        // Regions also replace the connection to `url` itself
+        let region_options = [
+            ("paths", !entry.paths.is_empty()),
+            ("watch_changes", entry.watch_changes),
+            ("tcp_check", entry.tcp_check),
+            ("cert_sha256", !entry.cert_sha256.is_empty()),
+            ("check_ocsp_stapling", entry.check_ocsp_stapling),
+        ];
+        let all_options: Vec<(&str, bool)> = response_options.iter().chain(&region_options).copied().collect();
+        // A redirect has no body of interest, and a 304 would say nothing about where it points. Login flows
+        // often set their session cookie on a redirect, so cookies are still checked.
+        let redirect_options: Vec<(&str, bool)> = response_options
+            .iter()
+            .filter(|(option, _)| {
+                !matches!(
+                    *option,
+                    "readiness" | "expect_redirect" | "expect_cookies" | "measure_ttfb" | "degraded_ttfb_ms"
+                )
+            })
+            .copied()
+            .chain([("watch_changes", entry.watch_changes)])
+            .collect();
+        // The module's verdict replaces the built-in response checks
+        let wasm_options: Vec<(&str, bool)> = response_options
+            .iter()
+            .filter(|(option, _)| {
+                !matches!(*option, "readiness" | "wasm_check" | "measure_ttfb" | "degraded_ttfb_ms")
+            })
+            .copied()
+            .collect();
+        let grouped = [
+            ("paths", !entry.paths.is_empty(), &response_options[..]),
+            ("regions", !entry.regions.is_empty(), &all_options[..]),
+            ("expect_redirect", entry.expect_redirect.is_some(), &redirect_options[..]),
+            ("wasm_check", entry.wasm_check.is_some(), &wasm_options[..]),
+        ];
+        for (group, used, options) in grouped {
+            let conflicting: Vec<&str> = options
+                .iter()
+                .filter_map(|(option, set)| set.then_some(*option))
+                .collect();
+            if used && !conflicting.is_empty() {
+                return Err(format!(
+                    "{} defines {}, which cannot be combined with {}",
+                    entry.url,
+                    group,
+                    conflicting.join(", ")
+                )
+                .into());
+            }
+        }
+        if let Some(comparison) = &entry.compare_latency {
+            if !(comparison.max_slower_percent.is_finite() && comparison.max_slower_percent > 0.0) {
+                return Err(format!("compare_latency.max_slower_percent for {} must be a positive number", entry.url).into());
+            }
+            if comparison.window == 0 {
+                return Err(format!("compare_latency.window for {} must be at least 1", entry.url).into());
+            }
+        }
+        if let Some(consistency) = &entry.latency_consistency {
+            if !(consistency.max_cv.is_finite() && consistency.max_cv > 0.0) {
+                return Err(format!("latency_consistency.max_cv for {} must be a positive number", entry.url).into());
+            }
+            if consistency.window < 3 {
+                return Err(format!("latency_consistency.window for {} must be at least 3", entry.url).into());
+            }
+        }
+        if entry.redirect_status.is_some() && entry.expect_redirect.is_none() {
+            return Err(format!("redirect_status for {} requires expect_redirect", entry.url).into());
+        }
+        if let Some(codes) = &entry.redirect_status {
+            if !codes.codes().iter().all(|code| (300..400).contains(code)) {
+                return Err(format!("redirect_status for {} must only list 3xx codes, got {}", entry.url, codes).into());
+            }
+        }
+        let expect_redirect = match &entry.expect_redirect {
+            Some(target) => Some(
+                Url::parse(&entry.url)
+                    .and_then(|url| url.join(target))
+                    .map_err(|_| format!("Invalid expect_redirect '{}' for {}", target, entry.url))?,
+            ),
+            None => None,
+        };
+        if entry.must_contain.iter().chain(&entry.must_not_contain).any(|text| text.is_empty()) {
+            return Err(format!("must_contain and must_not_contain for {} must not include empty strings", entry.url).into());
+        }
+        if entry.watch_changes && !entry.paths.is_empty() {
+            return Err(format!("{} defines paths, which cannot be combined with watch_changes", entry.url).into());
+        }
+        if entry.tcp_check && !is_http_url(&entry.url) {
+            return Err(format!("tcp_check for {} requires an http:// or https:// URL", entry.url).into());
+        }
+        if entry.alert_on_change && !entry.watch_changes {
+            return Err(format!("alert_on_change for {} requires watch_changes", entry.url).into());
+        }
+        if let Ok(base) = Url::parse(&entry.url) {
+            for check in &entry.paths {
+                base.join(&check.path)
+                    .map_err(|_| format!("Invalid path '{}' for {}", check.path, entry.url))?;
+            }
+        }
+
+        let method = match &entry.method {
+            Some(method) => Method::from_bytes(method.to_uppercase().as_bytes())
+                .map_err(|_| format!("Invalid method '{}' for {}", method, entry.url))?,
+            None => Method::GET,
+        };
+        if let (Some(degraded), Some(down)) = (entry.degraded_latency_ms, entry.down_latency_ms) {
+            if down <= degraded {
+                return Err(format!(
+                    "down_latency_ms for {} must be greater than degraded_latency_ms ({} <= {})",
+                    entry.url, down, degraded
+                )
+                .into());
+            }
+        }
+        if entry.http_1_0 && !is_http_url(&entry.url) {
+            return Err(format!("http_1_0 for {} requires an http:// or https:// URL", entry.url).into());
+        }
+        if (entry.measure_ttfb || entry.degraded_ttfb_ms.is_some()) && !is_http_url(&entry.url) {
+            return Err(format!("measure_ttfb for {} requires an http:// or https:// URL", entry.url).into());
+        }
+        if entry.stuck_after.is_some_and(|after| after < 2) {
+            return Err(format!("stuck_after for {} must be at least 2", entry.url).into());
+        }
+        if entry.stuck_after.is_some() && entry.watch_changes {
+            // A 304 answer has no body to compare
+            return Err(format!("stuck_after for {} cannot be combined with watch_changes", entry.url).into());
+        }
+        let compressed_body = match (&entry.body, entry.compress_body) {
+            (Some(body), true) => Some(gzip(body.as_bytes())?.into()),
+            (None, true) => return Err(format!("compress_body for {} requires a body", entry.url).into()),
+            (_, false) => None,
+        };
+        if entry.min_body_bytes.is_some() && method == Method::HEAD {
+            return Err(format!("min_body_bytes for {} cannot be used with HEAD, which has no body", entry.url).into());
+        }
+        if entry.stuck_after.is_some() && method == Method::HEAD {
+            return Err(format!("stuck_after for {} cannot be used with HEAD, which has no body", entry.url).into());
+        }
+
+        let json_schema = match entry.json_schema {
+            Some(path) => Some(load_json_schema(&base_dir.join(path), &entry.url)?),
+            None => None,
+        };
+
+        if !entry.cert_sha256.is_empty()
+            && !Url::parse(&entry.url).is_ok_and(|url| matches!(url.scheme(), "https" | "grpcs"))
+        {
+            return Err(format!("cert_sha256 for {} requires an https:// or grpcs:// URL", entry.url).into());
+        }
+        if entry.check_ocsp_stapling
+            && !Url::parse(&entry.url).is_ok_and(|url| matches!(url.scheme(), "https" | "grpcs"))
+        {
+            return Err(format!("check_ocsp_stapling for {} requires an https:// or grpcs:// URL", entry.url).into());
+        }
+        let mentions = parse_mentions(&entry.mentions, &entry.url)?;
+
+        let oauth = match &entry.oauth {
+            Some(oauth) => Some(Arc::new(
+                TokenProvider::new(oauth).map_err(|e| format!("Invalid oauth for {}: {}", entry.url, e))?,
+            )),
+            None => None,
+        };
+
+        let bearer_token_file = match &entry.bearer_token_file {
+            Some(_) if entry.oauth.is_some() => {
+                return Err(format!("{} defines both oauth and bearer_token_file; use one of them", entry.url).into());
+            }
+            Some(_) if !is_http_url(&entry.url) => {
+                return Err(format!("bearer_token_file for {} requires an http:// or https:// URL", entry.url).into());
+            }
+            Some(path) => Some(Arc::new(
+                TokenFile::new(&base_dir.join(path))
+                    .map_err(|e| format!("Invalid bearer_token_file for {}: {}", entry.url, e))?,
+            )),
+            None => None,
+        };
+
+        let wasm_check = match &entry.wasm_check {
+            Some(_) if !is_http_url(&entry.url) => {
+                return Err(format!("wasm_check for {} requires an http:// or https:// URL", entry.url).into());
+            }
+            Some(config) => Some(Arc::new(
+                WasmCheck::new(config, base_dir).map_err(|e| format!("Invalid wasm_check for {}: {}", entry.url, e))?,
+            )),
+            None => None,
+        };
+
+        let signer = match &entry.signature {
+            Some(signature) => Some(
+                RequestSigner::new(signature).map_err(|e| format!("Invalid signature for {}: {}", entry.url, e))?,
+            ),
+            None => None,
+        };
+
+        if entry.samples == 0 {
+            return Err(format!("samples for {} must be a positive number", entry.url).into());
+        }
+        if entry.samples > 1 && !entry.retry_non_idempotent && !matches!(method, Method::GET | Method::HEAD) {
+            return Err(format!(
+                "{} uses samples with a {} check; set retry_non_idempotent to allow repeating it",
+                entry.url, method
+            )
+            .into());
+        }
+
+        let cert_fingerprints = entry
+            .cert_sha256
+            .iter()
+            .map(|value| {
+                tls::parse_fingerprint(value).ok_or_else(|| {
+                    format!("Invalid cert_sha256 '{}' for {}: expected 64 hex digits", value, entry.url)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let negotiation_headers = [
+            (reqwest::header::ACCEPT, "accept", &entry.accept),
+            (reqwest::header::ACCEPT_LANGUAGE, "accept_language", &entry.accept_language),
+            (reqwest::header::ACCEPT_ENCODING, "accept_encoding", &entry.accept_encoding),
+        ]
+        .into_iter()
+        .filter_map(|(header, option, value)| Some((header, option, value.as_ref()?)))
+        .map(|(header, option, value)| {
+            HeaderValue::from_str(value)
+                .map(|value| (header, value))
+                .map_err(|_| format!("Invalid {} '{}' for {}", option, value, entry.url))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+        let mut negotiation_headers = negotiation_headers;
+        if entry.no_cache {
+            // Pragma is for HTTP/1.0 caches that predate Cache-Control
+            negotiation_headers.push((reqwest::header::CACHE_CONTROL, HeaderValue::from_static("no-cache")));
+            negotiation_headers.push((reqwest::header::PRAGMA, HeaderValue::from_static("no-cache")));
+        }
+        if let Some(param) = &entry.cache_bust_param {
+            let url = http_url(&entry.url)
+                .ok_or_else(|| format!("cache_bust_param for {} requires an http:// or https:// URL", entry.url))?;
+            if param.is_empty() || !param.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+                return Err(format!(
+                    "cache_bust_param for {} must be a query parameter name of letters, digits, '-', '_' and '.'",
+                    entry.url
+                )
+                .into());
+            }
+            if url.query_pairs().any(|(name, _)| name == param.as_str()) {
+                let message = format!("cache_bust_param '{}' for {} is already a query parameter of the URL", param, entry.url);
+                return Err(message.into());
+            }
+        }
+
+        let dns_server = match &entry.dns_server {
+            Some(value) => Some(dns::parse_server(value).ok_or_else(|| {
+                format!("Invalid dns_server '{}' for {}: expected an IP address with an optional port", value, entry.url)
+            })?),
+            None => None,
+        };
+
+        let expect_headers = entry
+            .expect_headers
+            .iter()
+            .map(|(header, pattern)| {
+                let name = HeaderName::from_bytes(header.as_bytes())
+                    .map_err(|_| format!("Invalid header name '{}' in expect_headers for {}", header, entry.url))?;
+                let pattern = Regex::new(pattern)
+                    .map_err(|e| format!("Invalid pattern for header {} of {}: {}", header, entry.url, e))?;
+                Ok((name, pattern))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        for cookie in &entry.expect_cookies {
+            if cookie.name.is_empty() || cookie.name.contains(['=', ';', ' ']) {
+                return Err(format!("Invalid cookie name '{}' in expect_cookies for {}", cookie.name, entry.url).into());
+            }
+            if let Some(same_site) = &cookie.same_site {
+                if !["strict", "lax", "none"].contains(&same_site.to_lowercase().as_str()) {
+                    return Err(format!(
+                        "same_site of cookie {} for {} must be Strict, Lax or None, not '{}'",
+                        cookie.name, entry.url, same_site
+                    )
+                    .into());
+                }
+            }
+        }
+
+        if entry.regions.len() == 1 {
+            return Err(format!("regions for {} must list at least two regions", entry.url).into());
+        }
+        let regions = entry
+            .regions
+            .iter()
+            .map(|(region, value)| {
+                let url = http_url(value).ok_or_else(|| {
+                    format!("Region '{}' of {} must be an http:// or https:// URL: {}", region, entry.url, value)
+                })?;
+                Ok((region.clone(), url))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let region_compare = match &entry.region_compare {
+            Some(_) if regions.is_empty() => {
+                return Err(format!("region_compare for {} requires regions", entry.url).into());
+            }
+            Some(value) => Some(RegionCompare::parse(value).ok_or_else(|| {
+                format!(
+                    "Invalid region_compare '{}' for {}: expected a JSON path such as $.version or a header name",
+                    value, entry.url
+                )
+            })?),
+            None => None,
+        };
+        if matches!(region_compare, Some(RegionCompare::Json(_))) && method == Method::HEAD {
+            return Err(format!("region_compare for {} cannot read a JSON field with HEAD, which has no body", entry.url).into());
+        }
+
+        let assertions = entry
+            .assertions
+            .iter()
+            .map(|value| Assertion::parse(value).map_err(|e| format!("{} for {}", e, entry.url)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            url: entry.url,
+            name: entry.name,
+            tags: entry.tags,
+            json_schema,
+            readiness: entry.readiness,
+            compare_latency: entry.compare_latency,
+            latency_consistency: entry.latency_consistency,
+            paths: entry.paths,
+            flag: entry.flag,
+            depends_on: entry.depends_on,
+            expect_down: entry.expect_down,
+            regions,
+            region_compare,
+            method,
+            body: entry.body,
+            compressed_body,
+            retry_non_idempotent: entry.retry_non_idempotent,
+            degraded_latency: entry.degraded_latency_ms.map(Duration::from_millis),
+            down_latency: entry.down_latency_ms.map(Duration::from_millis),
+            http_1_0: entry.http_1_0,
+            measure_ttfb: entry.measure_ttfb || entry.degraded_ttfb_ms.is_some(),
+            degraded_ttfb: entry.degraded_ttfb_ms.map(Duration::from_millis),
+            track_dns: entry.track_dns,
+            cert_fingerprints,
+            check_ocsp_stapling: entry.check_ocsp_stapling,
+            expect_redirect,
+            redirect_status: entry.redirect_status,
+            allowed_redirect_hosts: entry
+                .allowed_redirect_hosts
+                .iter()
+                .map(|host| host.to_lowercase())
+                .collect(),
+            tcp_check: entry.tcp_check,
+            watch_changes: entry.watch_changes,
+            alert_on_change: entry.alert_on_change,
+            socks_proxy: entry.socks_proxy,
+            mentions,
+            samples: entry.samples,
+            sample_policy: entry.sample_policy,
+            signer,
+            oauth,
+            bearer_token_file,
+            expect_json: entry.expect_json,
+            assertions,
+            assertion_status: entry.assertion_status,
+            must_contain: entry.must_contain,
+            must_not_contain: entry.must_not_contain,
+            negotiation_headers,
+            cache_bust_param: entry.cache_bust_param,
+            min_body_bytes: entry.min_body_bytes,
+            dns_server,
+            stuck_after: entry.stuck_after,
+            expect_headers,
+            expect_cookies: entry.expect_cookies,
+            wasm_check,
+        })
+    }
+}
+
+/// Top-level layout of the optional `UPNOTIF_CONFIG_FILE`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    #[serde(default)]
+    urls: Vec<serde_json::Value>,
+    #[serde(default)]
+    host_delays_ms: HashMap<String, u64>,
+    /// Mentions added to every URL with a matching `key:value` tag.
+    #[serde(default)]
+    mentions_by_tag: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    alert_rules: Vec<AlertRule>,
+    #[serde(default)]
+    routes: Vec<Route>,
+}
+
+/// A URL entry in the config file, either a bare string or an object with options.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UrlEntry {
+    url: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+    #[serde(default)]
+    json_schema: Option<PathBuf>,
+    #[serde(default)]
+    readiness: Option<ReadinessCheck>,
+    #[serde(default)]
+    compare_latency: Option<LatencyComparison>,
+    #[serde(default)]
+    latency_consistency: Option<LatencyConsistency>,
+    #[serde(default)]
+    paths: Vec<PathCheck>,
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    compress_body: bool,
+    #[serde(default)]
+    retry_non_idempotent: bool,
+    #[serde(default)]
+    degraded_latency_ms: Option<u64>,
+    #[serde(default)]
+    down_latency_ms: Option<u64>,
+    #[serde(default)]
+    http_1_0: bool,
+    #[serde(default)]
+    measure_ttfb: bool,
+    #[serde(default)]
+    degraded_ttfb_ms: Option<u64>,
+    #[serde(default)]
+    track_dns: bool,
+    #[serde(default)]
+    cert_sha256: Vec<String>,
+    #[serde(default)]
+    check_ocsp_stapling: bool,
+    #[serde(default)]
+    allowed_redirect_hosts: Vec<String>,
+    #[serde(default)]
+    expect_redirect: Option<String>,
+    #[serde(default)]
+    redirect_status: Option<StatusCodes>,
+    #[serde(default)]
+    tcp_check: bool,
+    #[serde(default)]
+    watch_changes: bool,
+    #[serde(default)]
+    alert_on_change: bool,
+    #[serde(default = "default_socks_proxy")]
+    socks_proxy: bool,
+    #[serde(default)]
+    mentions: Vec<String>,
+    #[serde(default = "default_samples")]
+    samples: u32,
+    #[serde(default)]
+    sample_policy: SamplePolicy,
+    #[serde(default)]
+    signature: Option<SignatureConfig>,
+    #[serde(default)]
+    oauth: Option<OAuthConfig>,
+    #[serde(default)]
+    bearer_token_file: Option<PathBuf>,
+    #[serde(default)]
+    expect_json: bool,
+    #[serde(default)]
+    assertions: Vec<String>,
+    #[serde(default)]
+    assertion_status: AssertionStatus,
+    #[serde(default)]
+    must_contain: Vec<String>,
+    #[serde(default)]
+    must_not_contain: Vec<String>,
+    #[serde(default)]
+    accept: Option<String>,
+    #[serde(default)]
+    accept_language: Option<String>,
+    #[serde(default)]
+    accept_encoding: Option<String>,
+    #[serde(default)]
+    no_cache: bool,
+    #[serde(default)]
+    cache_bust_param: Option<String>,
+    #[serde(default)]
+    min_body_bytes: Option<usize>,
+    #[serde(default)]
+    dns_server: Option<String>,
+    #[serde(default)]
+    stuck_after: Option<u32>,
+    #[serde(default)]
+    expect_headers: BTreeMap<String, String>,
+    #[serde(default)]
+    expect_cookies: Vec<ExpectedCookie>,
+    #[serde(default)]
+    wasm_check: Option<WasmCheckConfig>,
+    #[serde(default)]
+    flag: Option<String>,
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    expect_down: bool,
+    #[serde(default)]
+    regions: BTreeMap<String, String>,
+    #[serde(default)]
+    region_compare: Option<String>,
+}
+
+fn default_samples() -> u32 {
+    1
+}
+
+fn default_socks_proxy() -> bool {
+    true
+}
+
+impl FileConfig {
+    /// Loads the config file, layering the overlay for `profile` on top of it if one is given.
+    fn load(path: &Path, profile: Option<&str>) -> Result<(Self, FileSources), Box<dyn std::error::Error>> {
+        let mut config = read_json_file(path)?;
+        let mut sources = FileSources {
+            base: path.to_path_buf(),
+            overlay: None,
+            changes: Vec::new(),
+            effective: serde_json::Value::Null,
+        };
+
+        if let Some(profile) = profile {
+            let overlay_path = profile_path(path, profile);
+            merge_json(&mut config, read_json_file(&overlay_path)?, "", &mut sources.changes);
+            info!("Loaded config profile '{}' from {}", profile, overlay_path.display());
+            sources.overlay = Some(overlay_path);
+        }
+
+        sources.effective = config.clone();
+        let file_config = serde_json::from_value(config)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        Ok((file_config, sources))
+    }
+
+    fn url_specs(&mut self, base_dir: &Path) -> Result<Vec<UrlSpec>, Box<dyn std::error::Error>> {
+        let mut specs = std::mem::take(&mut self.urls)
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| match value {
+                serde_json::Value::String(url) => Ok(UrlSpec::plain(url)),
+                value => {
+                    let entry: UrlEntry = serde_json::from_value(value)
+                        .map_err(|e| format!("Invalid entry {} in config file urls: {}", index, e))?;
+                    UrlSpec::from_entry(entry, base_dir)
+                }
+            })
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+        // URLs with the same client share one token instead of each fetching their own
+        let mut providers: HashMap<String, Arc<TokenProvider>> = HashMap::new();
+        for spec in &mut specs {
+            if let Some(oauth) = &mut spec.oauth {
+                *oauth = providers.entry(oauth.key()).or_insert_with(|| oauth.clone()).clone();
+            }
+        }
+
+        // Dependencies and latency references may be given by URL or name, and are stored as URLs
+        let known: Vec<(String, Option<String>)> =
+            specs.iter().map(|spec| (spec.url.clone(), spec.name.clone())).collect();
+        // Being expected to be DOWN, these would hold back the alerts of everything depending on them
+        let expected_down: HashSet<String> =
+            specs.iter().filter(|spec| spec.expect_down).map(|spec| spec.url.clone()).collect();
+        for spec in &mut specs {
+            if let Some(comparison) = &mut spec.compare_latency {
+                let url = known
+                    .iter()
+                    .find(|(url, name)| *url == comparison.with || name.as_ref() == Some(&comparison.with))
+                    .map(|(url, _)| url.clone())
+                    .ok_or_else(|| {
+                        format!("{} compares latency with {}, which is not a configured URL", spec.url, comparison.with)
+                    })?;
+                if url == spec.url {
+                    return Err(format!("{} cannot compare latency with itself", spec.url).into());
+                }
+                comparison.with = url;
+            }
+            for dependency in &mut spec.depends_on {
+                let url = known
+                    .iter()
+                    .find(|(url, name)| url == dependency || name.as_ref() == Some(dependency))
+                    .map(|(url, _)| url.clone())
+                    .ok_or_else(|| format!("{} depends on {}, which is not a configured URL", spec.url, dependency))?;
+                if url == spec.url {
+                    return Err(format!("{} cannot depend on itself", spec.url).into());
+                }
+                if expected_down.contains(&url) {
+                    return Err(format!("{} depends on {}, which sets expect_down", spec.url, dependency).into());
+                }
+                *dependency = url;
+            }
+        }
+        if let Some(cycle) = dependency_cycle(&specs) {
+            return Err(format!("URL dependencies form a cycle: {}", cycle.join(" -> ")).into());
+        }
+
+        for (selector, values) in &self.mentions_by_tag {
+            let (key, value) = selector
+                .split_once(':')
+                .ok_or_else(|| format!("Invalid mentions_by_tag key '{}', expected tag:value", selector))?;
+            let mentions = parse_mentions(values, selector)?;
+            for spec in specs.iter_mut().filter(|spec| spec.tags.get(key).is_some_and(|tag| tag == value)) {
+                for mention in &mentions {
+                    if !spec.mentions.contains(mention) {
+                        spec.mentions.push(mention.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(specs)
+    }
+}
+
+/// Average of the last `window` response times in milliseconds, once that many were recorded.
+fn average_ms(samples: &VecDeque<Duration>, window: usize) -> Option<f64> {
+    if samples.len() < window {
+        return None;
+    }
+    let total: f64 = samples.iter().rev().take(window).map(|latency| latency.as_secs_f64() * 1000.0).sum();
+    Some(total / window as f64)
+}
+
+/// Finds a chain of `depends_on` entries that leads back to where it started.
+fn dependency_cycle(specs: &[UrlSpec]) -> Option<Vec<String>> {
+    fn visit<'a>(
+        url: &'a str,
+        specs: &'a [UrlSpec],
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = path.iter().position(|seen| *seen == url) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|url| url.to_string()).collect();
+            cycle.push(url.to_string());
+            return Some(cycle);
+        }
+        if !done.insert(url) {
+            return None;
+        }
+        path.push(url);
+        let dependencies = specs.iter().find(|spec| spec.url == url).map_or(&[][..], |spec| &spec.depends_on[..]);
+        for dependency in dependencies {
+            if let Some(cycle) = visit(dependency, specs, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        None
+    }
+
+    let mut done = HashSet::new();
+    specs
+        .iter()
+        .find_map(|spec| visit(&spec.url, specs, &mut Vec::new(), &mut done))
+}
+
+fn read_json_file(path: &Path) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+    let value = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+    Ok(value)
+}
+
+/// The overlay for a profile sits next to the base file: `config.json` -> `config.staging.json`.
+fn profile_path(path: &Path, profile: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, profile, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, profile),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Applies `overlay` to `base` with JSON Merge Patch (RFC 7386) semantics: objects are merged
+/// key by key, `null` removes a key, and any other value (including arrays) replaces it.
+fn merge_json(base: &mut serde_json::Value, overlay: serde_json::Value, path: &str, changes: &mut Vec<OverlayChange>) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let path = provenance::join(path, &key);
+                if value.is_null() {
+                    if let Some(previous) = base.remove(&key) {
+                        changes.push(OverlayChange {
+                            path,
+                            previous: Some(previous),
+                            value: None,
+                        });
+                    }
+                } else {
+                    merge_json(base.entry(key).or_insert(serde_json::Value::Null), value, &path, changes);
+                }
+            }
+        }
+        (base, overlay) => {
+            let previous = std::mem::replace(base, overlay.clone());
+            changes.push(OverlayChange {
+                path: path.to_string(),
+                previous: (!previous.is_null()).then_some(previous),
+                value: Some(overlay),
+            });
+        }
+    }
+}
+
+fn parse_mentions(values: &[String], context: &str) -> Result<Vec<Mention>, Box<dyn std::error::Error>> {
+    values
+        .iter()
+        .map(|value| {
+            Mention::parse(value).ok_or_else(|| {
+                format!(
+                    "Invalid mention '{}' for {}: expected a Slack user ID (U...), user group ID (S...), here or channel",
+                    value, context
+                )
+                .into()
+            })
+        })
+        .collect()
+}
+
+/// Tag keys double as metric label names, so they follow the same character rules.
+fn is_label_safe(key: &str) -> bool {
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn load_json_schema(path: &Path, url: &str) -> Result<jsonschema::Validator, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read JSON schema {} for {}: {}", path.display(), url, e))?;
+    let schema: serde_json::Value = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid JSON schema {} for {}: {}", path.display(), url, e))?;
+
+    jsonschema::validator_for(&schema)
+        .map_err(|e| format!("Invalid JSON schema {} for {}: {}", path.display(), url, e).into())
+}
+
+struct Config {
+    urls: Vec<UrlSpec>,
+    slack_webhook: String,
+    slack_colors: SeverityColors,
+    event_webhook: Option<String>,
+    /// Unix domain socket that local consumers read status events from.
+    event_socket: Option<PathBuf>,
+    /// Where `/metrics` is served for Prometheus.
+    metrics_address: Option<SocketAddr>,
+    /// Where the control endpoint for deploy windows is served.
+    control_address: Option<SocketAddr>,
+    control_token: Option<String>,
+    /// OTLP collector that receives a span for every check.
+    otlp: Option<OtlpConfig>,
+    /// Upper bounds of the response time histogram buckets, in seconds.
+    metrics_buckets: Vec<f64>,
+    /// Feature-flag endpoint deciding which flagged URLs are checked.
+    flag_url: Option<String>,
+    flag_refresh: Duration,
+    interval_seconds: u64,
+    overrun_policy: OverrunPolicy,
+    /// Start check cycles on wall-clock multiples of the interval, e.g. at :00 of every minute.
+    align_checks: bool,
+    first_check_delay: Option<Duration>,
+    request_timeout: Duration,
+    connect_timeout: Duration,
+    /// Source address of outbound check connections.
+    local_address: Option<IpAddr>,
+    /// DNS server per monitored host that doesn't use the system resolver.
+    dns_servers: HashMap<String, SocketAddr>,
+    socks_proxy: Option<SocksProxy>,
+    batch_window_seconds: Option<u64>,
+    /// Whether a shutdown signal lets a running check cycle finish instead of abandoning it.
+    drain_on_shutdown: bool,
+    drain_timeout: Duration,
+    digest_interval: Option<Duration>,
+    /// Whether the current digest window is saved to the state file and continued after a restart.
+    resume_digest: bool,
+    /// How often the operational summary of the checks run is sent.
+    activity_interval: Option<Duration>,
+    host_delay: Duration,
+    host_delays: HashMap<String, Duration>,
+    notify_queue_size: usize,
+    /// Where undelivered notifications are kept so they survive a restart.
+    notify_queue_file: Option<PathBuf>,
+    /// Limit applied to each Slack webhook separately.
+    slack_rate_limit: Option<RateLimit>,
+    event_rate_limit: Option<RateLimit>,
+    duplicate_window: Duration,
+    max_message_len: Option<usize>,
+    message_overflow: Overflow,
+    show_tags: bool,
+    show_server_ip: bool,
+    show_version: bool,
+    /// Tag key that groups the startup report, with all-UP groups collapsed to a count.
+    startup_group_by: Option<String>,
+    detail_level: DetailLevel,
+    verify_notifier: NotifierCheck,
+    startup_mode: StartupMode,
+    /// Alert again when a URL stays DOWN but its failure category changes.
+    alert_on_failure_change: bool,
+    /// Notify when a feature flag pauses or resumes checks of a URL.
+    alert_on_pause_resume: bool,
+    shuffle_checks: bool,
+    startup_checks: u32,
+    startup_success_rate: f64,
+    startup_check_spacing: Duration,
+    snapshot: Option<SnapshotTarget>,
+    state_file: Option<PathBuf>,
+    csv_log: Option<CsvLog>,
+    notify_state_failures: bool,
+    alert_rules: Vec<AlertRule>,
+    routes: Vec<Route>,
+    retries: u32,
+    retry_delay: Duration,
+    request_id_header: Option<HeaderName>,
+    request_id_in_alerts: bool,
+    throttled_status: ThrottledStatus,
+    no_content_status: NoContentStatus,
+    honor_retry_after: bool,
+    detect_cross_host_redirects: bool,
+    detect_https_downgrade: bool,
+    latency_anomaly: Option<AnomalyDetection>,
+    warmup_interval: Option<Duration>,
+    failure_threshold: u32,
+    /// How long a DOWN URL must pass its checks before it is reported as recovered.
+    recovery_hold: Option<Duration>,
+    auto_disable_after: Option<u32>,
+    gone_status_codes: Vec<u16>,
+    broad_outage_min_down: Option<usize>,
+    /// Reference URL that must be UP for the other URLs to be checked and alerted on.
+    canary_url: Option<String>,
+    immediate_alert_codes: Vec<u16>,
+    display_timezone: Tz,
+    test_mode: bool,
+}
+
+impl Config {
+    fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let urls_str = provenance::var("UPNOTIF_URLS").ok();
+        let config_file = provenance::var("UPNOTIF_CONFIG_FILE").ok().map(PathBuf::from);
+        let profile = provenance::var("UPNOTIF_PROFILE").ok().filter(|profile| !profile.is_empty());
+
+        if let Some(profile) = &profile {
+            if config_file.is_none() {
+                return Err("UPNOTIF_PROFILE requires UPNOTIF_CONFIG_FILE".into());
+            }
+            if !profile.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err("UPNOTIF_PROFILE may only contain letters, digits, '-' and '_'".into());
+            }
+        }
+
+        if urls_str.is_none() && config_file.is_none() {
+            return Err("UPNOTIF_URLS or UPNOTIF_CONFIG_FILE environment variable is required".into());
+        }
+
+        let slack_webhook = provenance::var("UPNOTIF_SLACK_WEBHOOK")
+            .map_err(|_| "UPNOTIF_SLACK_WEBHOOK environment variable is required")?;
+
+        let slack_colors = match provenance::var("UPNOTIF_SLACK_COLORS") {
+            Ok(value) => SeverityColors::parse(&value)
+                .map_err(|e| format!("UPNOTIF_SLACK_COLORS: {}", e))?,
+            Err(_) => SeverityColors::default(),
+        };
+
+        let metrics_address = match provenance::var("UPNOTIF_METRICS_ADDRESS") {
+            Ok(value) => Some(
+                value
+                    .trim()
+                    .parse::<SocketAddr>()
+                    .map_err(|_| "UPNOTIF_METRICS_ADDRESS must be an address and port, e.g. 0.0.0.0:9090")?,
+            ),
+            Err(_) => None,
+        };
+        let control_address = match provenance::var("UPNOTIF_CONTROL_ADDRESS") {
+            Ok(value) => Some(
+                value
+                    .trim()
+                    .parse::<SocketAddr>()
+                    .map_err(|_| "UPNOTIF_CONTROL_ADDRESS must be an address and port, e.g. 127.0.0.1:9091")?,
+            ),
+            Err(_) => None,
+        };
+        let control_token = provenance::var("UPNOTIF_CONTROL_TOKEN").ok().filter(|token| !token.is_empty());
+        let metrics_buckets = match provenance::var("UPNOTIF_METRICS_BUCKETS") {
+            Ok(value) => metrics::parse_buckets(&value).ok_or(
+                "UPNOTIF_METRICS_BUCKETS must be comma-separated seconds in increasing order, e.g. 0.1,0.5,1,5",
+            )?,
+            Err(_) => metrics::DEFAULT_BUCKETS.to_vec(),
+        };
+
+        let flag_url = provenance::var("UPNOTIF_FLAG_URL").ok().filter(|url| !url.is_empty());
+        if flag_url.as_deref().is_some_and(|url| !is_http_url(url)) {
+            return Err("UPNOTIF_FLAG_URL must be an http:// or https:// URL".into());
+        }
+        let flag_refresh = Duration::from_secs(
+            provenance::var("UPNOTIF_FLAG_REFRESH_SECONDS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or("UPNOTIF_FLAG_REFRESH_SECONDS must be a positive number")?,
+        );
+
+        let otlp = OtlpConfig::from_env()?;
+        let event_socket = provenance::var("UPNOTIF_EVENT_SOCKET").ok().filter(|path| !path.is_empty()).map(PathBuf::from);
+        let event_webhook = provenance::var("UPNOTIF_EVENT_WEBHOOK").ok();
+        if let Some(event_webhook) = &event_webhook {
+            Url::parse(event_webhook)
+                .map_err(|_| "Invalid UPNOTIF_EVENT_WEBHOOK URL")?;
+        }
+
+        let mut interval_seconds = provenance::var("UPNOTIF_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse::<u64>()
+            .map_err(|_| "UPNOTIF_INTERVAL_SECONDS must be a valid number")?;
+        // A hard floor, so a bad value can't hammer the monitored services
+        if let Ok(value) = provenance::var("UPNOTIF_MIN_INTERVAL_SECONDS") {
+            let min_interval = value
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or("UPNOTIF_MIN_INTERVAL_SECONDS must be a positive number")?;
+            if interval_seconds < min_interval {
+                warn!(
+                    "UPNOTIF_INTERVAL_SECONDS={} is below UPNOTIF_MIN_INTERVAL_SECONDS, checking every {} seconds instead",
+                    interval_seconds, min_interval
+                );
+                interval_seconds = min_interval;
+            }
+        }
+
+        let overrun_policy =
+            OverrunPolicy::parse(&provenance::var("UPNOTIF_OVERRUN_POLICY").unwrap_or_else(|_| "skip".to_string()))
+                .ok_or("UPNOTIF_OVERRUN_POLICY must be skip or extend")?;
+        let align_checks = env_flag("UPNOTIF_ALIGN_CHECKS")?;
+        if align_checks && overrun_policy == OverrunPolicy::Extend {
+            return Err("UPNOTIF_ALIGN_CHECKS cannot be combined with UPNOTIF_OVERRUN_POLICY=extend".into());
+        }
+
+        let first_check_delay = match provenance::var("UPNOTIF_FIRST_CHECK_DELAY") {
+            Ok(value) => Some(Duration::from_secs(
+                value
+                    .parse::<u64>()
+                    .map_err(|_| "UPNOTIF_FIRST_CHECK_DELAY must be a valid number of seconds")?,
+            )),
+            Err(_) => None,
+        };
+
+        let request_timeout = provenance::var("UPNOTIF_REQUEST_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+            .ok_or("UPNOTIF_REQUEST_TIMEOUT_SECONDS must be a positive number")?;
+
+        let connect_timeout = provenance::var("UPNOTIF_CONNECT_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+            .ok_or("UPNOTIF_CONNECT_TIMEOUT_SECONDS must be a positive number")?;
+        if connect_timeout > request_timeout {
+            return Err("UPNOTIF_CONNECT_TIMEOUT_SECONDS must not exceed UPNOTIF_REQUEST_TIMEOUT_SECONDS".into());
+        }
+
+        let local_address = match provenance::var("UPNOTIF_LOCAL_ADDRESS") {
+            Ok(value) => {
+                let address: IpAddr = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("UPNOTIF_LOCAL_ADDRESS must be an IP address, got '{}'", value))?;
+                // Fail at startup rather than on every check when the address isn't assigned to this host
+                std::net::TcpListener::bind((address, 0))
+                    .map_err(|e| format!("UPNOTIF_LOCAL_ADDRESS {} cannot be used: {}", address, e))?;
+                Some(address)
+            }
+            Err(_) => None,
+        };
+
+        let digest_interval = match provenance::var("UPNOTIF_DIGEST_INTERVAL_HOURS") {
+            Ok(value) => Some(
+                value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|hours| *hours > 0)
+                    .map(|hours| Duration::from_secs(hours * 3600))
+                    .ok_or("UPNOTIF_DIGEST_INTERVAL_HOURS must be a positive number")?,
+            ),
+            Err(_) => None,
+        };
+
+        let activity_interval = match provenance::var("UPNOTIF_ACTIVITY_INTERVAL_MINUTES") {
+            Ok(value) => Some(
+                value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|minutes| *minutes > 0)
+                    .map(|minutes| Duration::from_secs(minutes * 60))
+                    .ok_or("UPNOTIF_ACTIVITY_INTERVAL_MINUTES must be a positive number")?,
+            ),
+            Err(_) => None,
+        };
+
+        let batch_window_seconds = match provenance::var("UPNOTIF_BATCH_WINDOW_SECONDS") {
+            Ok(value) => Some(
+                value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or("UPNOTIF_BATCH_WINDOW_SECONDS must be a positive number")?,
+            ),
+            Err(_) => None,
+        };
+
+        let drain_on_shutdown = env_flag("UPNOTIF_DRAIN_ON_SHUTDOWN")?;
+        let drain_timeout = Duration::from_secs(
+            provenance::var("UPNOTIF_DRAIN_TIMEOUT_SECONDS")
+                .unwrap_or_else(|_| "8".to_string())
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or("UPNOTIF_DRAIN_TIMEOUT_SECONDS must be a positive number")?,
+        );
+
+        let host_delay = Duration::from_millis(
+            provenance::var("UPNOTIF_HOST_DELAY_MS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse::<u64>()
+                .map_err(|_| "UPNOTIF_HOST_DELAY_MS must be a valid number")?,
+        );
+
+        let slack_rate_limit = match provenance::var("UPNOTIF_SLACK_RATE_LIMIT") {
+            Ok(value) => Some(
+                RateLimit::parse(&value)
+                    .ok_or("UPNOTIF_SLACK_RATE_LIMIT must look like 20/minute (per second, minute or hour)")?,
+            ),
+            Err(_) => None,
+        };
+        let event_rate_limit = match provenance::var("UPNOTIF_EVENT_RATE_LIMIT") {
+            Ok(value) => Some(
+                RateLimit::parse(&value)
+                    .ok_or("UPNOTIF_EVENT_RATE_LIMIT must look like 60/minute (per second, minute or hour)")?,
+            ),
+            Err(_) => None,
+        };
+
+        let notify_queue_size = provenance::var("UPNOTIF_NOTIFY_QUEUE_SIZE")
+            .unwrap_or_else(|_| "50".to_string())
+            .parse::<usize>()
+            .map_err(|_| "UPNOTIF_NOTIFY_QUEUE_SIZE must be a valid number")?;
+        let notify_queue_file = provenance::var("UPNOTIF_NOTIFY_QUEUE_FILE")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from);
+        if notify_queue_file.is_some() && notify_queue_size == 0 {
+            return Err("UPNOTIF_NOTIFY_QUEUE_FILE requires a UPNOTIF_NOTIFY_QUEUE_SIZE above 0".into());
+        }
+
+        let duplicate_window = Duration::from_secs(
+            provenance::var("UPNOTIF_DUPLICATE_WINDOW_SECONDS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse::<u64>()
+                .map_err(|_| "UPNOTIF_DUPLICATE_WINDOW_SECONDS must be a valid number")?,
+        );
+
+        let max_message_len = match provenance::var("UPNOTIF_MAX_MESSAGE_LENGTH") {
+            Ok(value) => Some(
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|len| *len >= MIN_MESSAGE_LEN)
+                    .ok_or_else(|| {
+                        format!("UPNOTIF_MAX_MESSAGE_LENGTH must be a number of at least {}", MIN_MESSAGE_LEN)
+                    })?,
+            ),
+            Err(_) => None,
+        };
+
+        let message_overflow = Overflow::parse(
+            &provenance::var("UPNOTIF_MESSAGE_OVERFLOW").unwrap_or_else(|_| "split".to_string()),
+        )
+        .ok_or("UPNOTIF_MESSAGE_OVERFLOW must be split or truncate")?;
+
+        let show_tags = env_flag("UPNOTIF_SHOW_TAGS")?;
+        let show_server_ip = env_flag("UPNOTIF_SHOW_SERVER_IP")?;
+        let show_version = env_flag("UPNOTIF_SHOW_VERSION")?;
+        let startup_group_by = match provenance::var("UPNOTIF_STARTUP_GROUP_BY") {
+            Ok(key) if is_label_safe(key.trim()) => Some(key.trim().to_string()),
+            Ok(_) => {
+                return Err("UPNOTIF_STARTUP_GROUP_BY must be a tag key of letters, digits and underscores".into());
+            }
+            Err(_) => None,
+        };
+
+        let startup_checks = provenance::var("UPNOTIF_STARTUP_CHECKS")
+            .unwrap_or_else(|_| "1".to_string())
+            .parse::<u32>()
+            .ok()
+            .filter(|checks| *checks > 0)
+            .ok_or("UPNOTIF_STARTUP_CHECKS must be a positive number")?;
+
+        let startup_success_rate = provenance::var("UPNOTIF_STARTUP_SUCCESS_RATE")
+            .unwrap_or_else(|_| "1.0".to_string())
+            .parse::<f64>()
+            .ok()
+            .filter(|rate| (0.0..=1.0).contains(rate))
+            .ok_or("UPNOTIF_STARTUP_SUCCESS_RATE must be a number between 0 and 1")?;
+
+        let startup_check_spacing = Duration::from_secs(
+            provenance::var("UPNOTIF_STARTUP_CHECK_SPACING_SECONDS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse::<u64>()
+                .map_err(|_| "UPNOTIF_STARTUP_CHECK_SPACING_SECONDS must be a valid number")?,
+        );
+
+        let retries = provenance::var("UPNOTIF_RETRIES")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<u32>()
+            .map_err(|_| "UPNOTIF_RETRIES must be a valid number")?;
+
+        let retry_delay = Duration::from_millis(
+            provenance::var("UPNOTIF_RETRY_DELAY_MS")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse::<u64>()
+                .map_err(|_| "UPNOTIF_RETRY_DELAY_MS must be a valid number")?,
+        );
+
+        let state_file = provenance::var("UPNOTIF_STATE_FILE")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from);
+        let notify_state_failures = env_flag("UPNOTIF_NOTIFY_STATE_FAILURES")?;
+        let startup_mode =
+            StartupMode::parse(&provenance::var("UPNOTIF_STARTUP_MODE").unwrap_or_else(|_| "report".to_string()))
+                .ok_or("UPNOTIF_STARTUP_MODE must be report or handoff")?;
+        if startup_mode == StartupMode::Handoff && state_file.is_none() {
+            return Err("UPNOTIF_STARTUP_MODE=handoff requires UPNOTIF_STATE_FILE".into());
+        }
+        let resume_digest = env_flag("UPNOTIF_DIGEST_RESUME")?;
+        if resume_digest && state_file.is_none() {
+            return Err("UPNOTIF_DIGEST_RESUME requires UPNOTIF_STATE_FILE".into());
+        }
+        if resume_digest && digest_interval.is_none() {
+            return Err("UPNOTIF_DIGEST_RESUME requires UPNOTIF_DIGEST_INTERVAL_HOURS".into());
+        }
+
+        let csv_log = provenance::var("UPNOTIF_CSV_LOG")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(|value| CsvLog::new(PathBuf::from(value)));
+
+        let request_id_header = match provenance::var("UPNOTIF_REQUEST_ID_HEADER") {
+            Ok(value) if !value.trim().is_empty() => Some(
+                HeaderName::from_bytes(value.trim().as_bytes())
+                    .map_err(|_| "UPNOTIF_REQUEST_ID_HEADER must be a valid HTTP header name")?,
+            ),
+            _ => None,
+        };
+        let request_id_in_alerts = env_flag("UPNOTIF_REQUEST_ID_IN_ALERTS")?;
+
+        let throttled_status = ThrottledStatus::parse(
+            &provenance::var("UPNOTIF_THROTTLED_STATUS").unwrap_or_else(|_| "throttled".to_string()),
+        )
+        .ok_or("UPNOTIF_THROTTLED_STATUS must be throttled, up or down")?;
+        let no_content_status =
+            NoContentStatus::parse(&provenance::var("UPNOTIF_NO_CONTENT_STATUS").unwrap_or_else(|_| "fail".to_string()))
+                .ok_or("UPNOTIF_NO_CONTENT_STATUS must be up or fail")?;
+        let honor_retry_after = env_flag("UPNOTIF_HONOR_RETRY_AFTER")?;
+        let detect_cross_host_redirects = env_flag("UPNOTIF_DETECT_CROSS_HOST_REDIRECTS")?;
+        let shuffle_checks = env_flag("UPNOTIF_SHUFFLE_CHECKS")?;
+        let detail_level = DetailLevel::parse(&provenance::var("UPNOTIF_DETAIL_LEVEL").unwrap_or_else(|_| "normal".to_string()))
+            .ok_or("UPNOTIF_DETAIL_LEVEL must be terse, normal or verbose")?;
+        let verify_notifier =
+            NotifierCheck::parse(&provenance::var("UPNOTIF_VERIFY_NOTIFIER").unwrap_or_else(|_| "off".to_string()))
+                .ok_or("UPNOTIF_VERIFY_NOTIFIER must be off, warn or exit")?;
+        let detect_https_downgrade = env_flag("UPNOTIF_DETECT_HTTPS_DOWNGRADE")?;
+        let alert_on_failure_change = env_flag("UPNOTIF_ALERT_ON_FAILURE_CHANGE")?;
+        let alert_on_pause_resume = env_flag("UPNOTIF_ALERT_ON_PAUSE_RESUME")?;
+
+        let failure_threshold = provenance::var("UPNOTIF_FAILURE_THRESHOLD")
+            .unwrap_or_else(|_| "1".to_string())
+            .parse::<u32>()
+            .ok()
+            .filter(|threshold| *threshold > 0)
+            .ok_or("UPNOTIF_FAILURE_THRESHOLD must be a positive number")?;
+        let recovery_hold = match provenance::var("UPNOTIF_RECOVERY_HOLD_SECONDS") {
+            Ok(value) => Some(Duration::from_secs(
+                value
+                    .parse::<u64>()
+                    .map_err(|_| "UPNOTIF_RECOVERY_HOLD_SECONDS must be a valid number of seconds")?,
+            ))
+            .filter(|hold| !hold.is_zero()),
+            Err(_) => None,
+        };
+
+        let auto_disable_after = match provenance::var("UPNOTIF_AUTO_DISABLE_AFTER") {
+            Ok(value) => Some(
+                value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|checks| *checks > 0)
+                    .ok_or("UPNOTIF_AUTO_DISABLE_AFTER must be a positive number")?,
+            ),
+            Err(_) => None,
+        };
+
+        let gone_status_codes = provenance::var("UPNOTIF_GONE_STATUS_CODES")
+            .unwrap_or_else(|_| "404,410".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .map(|code| {
+                code.parse::<u16>()
+                    .ok()
+                    .filter(|code| (100..600).contains(code))
+                    .ok_or_else(|| format!("Invalid status code '{}' in UPNOTIF_GONE_STATUS_CODES", code))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let broad_outage_min_down = match provenance::var("UPNOTIF_BROAD_OUTAGE_MIN_DOWN") {
+            Ok(value) => Some(
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|count| *count >= 2)
+                    .ok_or("UPNOTIF_BROAD_OUTAGE_MIN_DOWN must be a number of at least 2")?,
+            ),
+            Err(_) => None,
+        };
+
+        let canary_url = provenance::var("UPNOTIF_CANARY_URL").ok().filter(|url| !url.is_empty());
+        if let Some(canary_url) = &canary_url {
+            if !is_http_url(canary_url) {
+                return Err("UPNOTIF_CANARY_URL must be an http:// or https:// URL".into());
+            }
+        }
+
+        let immediate_alert_codes = provenance::var("UPNOTIF_IMMEDIATE_ALERT_STATUS_CODES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .map(|code| {
+                code.parse::<u16>()
+                    .ok()
+                    .filter(|code| (100..600).contains(code))
+                    .ok_or_else(|| format!("Invalid status code '{}' in UPNOTIF_IMMEDIATE_ALERT_STATUS_CODES", code))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let display_timezone = match provenance::var("UPNOTIF_TIMEZONE") {
+            Ok(value) => value
+                .trim()
+                .parse::<Tz>()
+                .map_err(|_| format!("UPNOTIF_TIMEZONE '{}' is not a known time zone, e.g. Europe/Stockholm", value))?,
+            Err(_) => Tz::UTC,
+        };
+
+        let warmup_interval = match provenance::var("UPNOTIF_WARMUP_INTERVAL_SECONDS") {
+            Ok(value) => Some(Duration::from_secs(
+                value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or("UPNOTIF_WARMUP_INTERVAL_SECONDS must be a positive number")?,
+            )),
+            Err(_) => None,
+        };
+
+        let latency_anomaly = match provenance::var("UPNOTIF_LATENCY_ANOMALY_SIGMA") {
+            Ok(value) => Some(AnomalyDetection {
+                sigma: value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|sigma| *sigma > 0.0)
+                    .ok_or("UPNOTIF_LATENCY_ANOMALY_SIGMA must be a positive number")?,
+                warmup: provenance::var("UPNOTIF_LATENCY_ANOMALY_WARMUP")
+                    .unwrap_or_else(|_| "30".to_string())
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|warmup| *warmup >= 2)
+                    .ok_or("UPNOTIF_LATENCY_ANOMALY_WARMUP must be a number of at least 2")?,
+            }),
+            Err(_) => None,
+        };
+
+        let snapshot = provenance::var("UPNOTIF_SNAPSHOT_FILE")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(|value| SnapshotTarget::parse(&value));
+
+        let mut urls: Vec<UrlSpec> = urls_str
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .map(UrlSpec::plain)
+            .collect();
+
+        let mut host_delays = HashMap::new();
+        let mut alert_rules = Vec::new();
+        let mut routes = Vec::new();
+        let mut url_sources: HashMap<String, String> =
+            urls.iter().map(|spec| (spec.url.clone(), "UPNOTIF_URLS".to_string())).collect();
+        let mut file_sources = None;
+
+        if let Some(path) = &config_file {
+            let (mut file_config, sources) = FileConfig::load(path, profile.as_deref())?;
+            let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+            let file_urls = file_config.url_specs(base_dir)?;
+            for (index, spec) in file_urls.iter().enumerate() {
+                let source = match &sources.overlay {
+                    Some(overlay) if sources.set_by_overlay(&format!("urls[{}]", index)) => overlay,
+                    _ => &sources.base,
+                };
+                url_sources.entry(spec.url.clone()).or_insert_with(|| source.display().to_string());
+            }
+            urls.extend(file_urls);
+            file_sources = Some(sources);
+            host_delays = file_config
+                .host_delays_ms
+                .into_iter()
+                .map(|(host, ms)| (host.to_lowercase(), Duration::from_millis(ms)))
+                .collect();
+            alert_rules = file_config.alert_rules;
+            routes = file_config.routes;
+        }
+
+        for rule in &alert_rules {
+            rule.validate()?;
+        }
+        for route in &routes {
+            route.validate()?;
+        }
+
+        if urls.is_empty() {
+            return Err("At least one URL must be provided in UPNOTIF_URLS or the config file".into());
+        }
+
+        // Validate URLs
+        let mut seen = HashSet::new();
+        for spec in &urls {
+            let url = Url::parse(&spec.url)
+                .map_err(|_| format!("Invalid URL: {}", spec.url))?;
+            if !SUPPORTED_SCHEMES.contains(&url.scheme()) {
+                return Err(format!(
+                    "Unsupported scheme '{}' in URL {}; supported schemes are {}",
+                    url.scheme(),
+                    spec.url,
+                    SUPPORTED_SCHEMES.join(", ")
+                )
+                .into());
+            }
+            if !seen.insert(&spec.url) {
+                return Err(format!("Duplicate URL: {}", spec.url).into());
+            }
+        }
+
+        if let Some(filter) = provenance::var("UPNOTIF_FILTER").ok().filter(|value| !value.trim().is_empty()) {
+            let configured = urls.len();
+            urls.retain(|spec| matches_filter(spec, &filter));
+            if urls.is_empty() {
+                return Err(format!(
+                    "UPNOTIF_FILTER '{}' matched none of the {} configured URLs",
+                    filter, configured
+                )
+                .into());
+            }
+            info!(
+                "UPNOTIF_FILTER '{}' included {} URLs and excluded {}",
+                filter,
+                urls.len(),
+                configured - urls.len()
+            );
+        }
+
+        if let Ok(value) = provenance::var("UPNOTIF_SAMPLE_PERCENT") {
+            let percent = value
+                .parse::<f64>()
+                .ok()
+                .filter(|percent| *percent > 0.0 && *percent <= 100.0)
+                .ok_or("UPNOTIF_SAMPLE_PERCENT must be a number greater than 0 and at most 100")?;
+            let configured = urls.len();
+            urls.retain(|spec| is_sampled(&spec.url, percent));
+            if urls.is_empty() {
+                return Err(format!(
+                    "UPNOTIF_SAMPLE_PERCENT={} selected none of the {} configured URLs",
+                    value, configured
+                )
+                .into());
+            }
+            info!("Sampling {} of {} configured URLs ({}%)", urls.len(), configured, percent);
+        }
+
+        let socks_proxy = match provenance::var("UPNOTIF_SOCKS_PROXY") {
+            Ok(value) => Some(SocksProxy::new(&value, &urls)?),
+            Err(_) => None,
+        };
+
+        let default_dns_server = match provenance::var("UPNOTIF_DNS_SERVER") {
+            Ok(value) => Some(dns::parse_server(&value).ok_or_else(|| {
+                format!("UPNOTIF_DNS_SERVER must be an IP address with an optional port, got '{}'", value)
+            })?),
+            Err(_) => None,
+        };
+        let dns_servers = dns_servers(default_dns_server, &urls)?;
+        if let (None, Some(spec)) = (&flag_url, urls.iter().find(|spec| spec.flag.is_some())) {
+            return Err(format!("{} sets flag, which requires UPNOTIF_FLAG_URL", spec.url).into());
+        }
+
+        if let Some(key) = &startup_group_by {
+            if !urls.iter().any(|spec| spec.tags.contains_key(key)) {
+                warn!("UPNOTIF_STARTUP_GROUP_BY is {}, but no URL has that tag", key);
+            }
+        }
+
+        let test_mode = slack_webhook == "test";
+
+        if env_flag("UPNOTIF_DEBUG_CONFIG")? {
+            let monitored: Vec<(String, String)> = urls
+                .iter()
+                .map(|spec| (spec.url.clone(), url_sources.remove(&spec.url).unwrap_or_default()))
+                .collect();
+            provenance::report(file_sources.as_ref(), &monitored);
+        }
+
+        // Validate Slack webhook URL (unless in test mode)
+        if !test_mode {
+            Url::parse(&slack_webhook)
+                .map_err(|_| "Invalid Slack webhook URL")?;
+        }
+
+        Ok(Config {
+            urls,
+            slack_webhook,
+            slack_colors,
+            event_webhook,
+            event_socket,
+            metrics_address,
+            metrics_buckets,
+            control_address,
+            control_token,
+            otlp,
+            flag_url,
+            flag_refresh,
+            interval_seconds,
+            overrun_policy,
+            align_checks,
+            first_check_delay,
+            request_timeout,
+            connect_timeout,
+            local_address,
+            dns_servers,
+            socks_proxy,
+            batch_window_seconds,
+            drain_on_shutdown,
+            drain_timeout,
+            digest_interval,
+            resume_digest,
+            activity_interval,
+            host_delay,
+            host_delays,
+            notify_queue_size,
+            notify_queue_file,
+            slack_rate_limit,
+            event_rate_limit,
+            duplicate_window,
+            max_message_len,
+            message_overflow,
+            show_tags,
+            startup_group_by,
+            show_server_ip,
+            show_version,
+            detail_level,
+            verify_notifier,
+            alert_on_failure_change,
+            alert_on_pause_resume,
+            shuffle_checks,
+            startup_checks,
+            startup_success_rate,
+            startup_check_spacing,
+            snapshot,
+            state_file,
+            startup_mode,
+            csv_log,
+            notify_state_failures,
+            alert_rules,
+            routes,
+            retries,
+            retry_delay,
+            request_id_header,
+            request_id_in_alerts,
+            throttled_status,
+            no_content_status,
+            honor_retry_after,
+            detect_cross_host_redirects,
+            detect_https_downgrade,
+            latency_anomaly,
+            warmup_interval,
+            failure_threshold,
+            recovery_hold,
+            auto_disable_after,
+            gone_status_codes,
+            broad_outage_min_down,
+            canary_url,
+            immediate_alert_codes,
+            display_timezone,
+            test_mode,
+        })
+    }
+
+    /// Delay between consecutive requests to the same host within a cycle.
+    fn delay_for_host(&self, host: &str) -> Duration {
+        self.host_delays.get(host).copied().unwrap_or(self.host_delay)
+    }
+}
+
+/// Crate version plus the git commit it was built from, e.g. `v1.2.3 (abc1234)`.
+fn version_label() -> String {
+    let version = format!("v{}", env!("CARGO_PKG_VERSION"));
+    match env!("UPNOTIF_GIT_SHA") {
+        "" => version,
+        sha => format!("{} ({})", version, sha),
+    }
+}
+
+/// Whether a URL matches any term of a comma-separated `UPNOTIF_FILTER`.
+///
+/// `key:value` terms match tags exactly; other terms, including URL prefixes like `https://api`,
+/// match a case-insensitive substring of the URL or its name.
+fn matches_filter(spec: &UrlSpec, filter: &str) -> bool {
+    filter
+        .split(',')
+        .map(str::trim)
+        .filter(|term| !term.is_empty())
+        .any(|term| match term.split_once(':') {
+            // `https://...` is a URL fragment, not a tag named "https"
+            Some((key, value)) if is_label_safe(key) && !value.starts_with("//") => {
+                spec.tags.get(key).is_some_and(|tag| tag == value)
+            }
+            _ => {
+                let term = term.to_lowercase();
+                spec.url.to_lowercase().contains(&term)
+                    || spec
+                        .name
+                        .as_ref()
+                        .is_some_and(|name| name.to_lowercase().contains(&term))
+            }
+        })
+}
+
+/// Whether a URL falls into the sampled subset for the given percentage.
+///
+/// Uses FNV-1a rather than `DefaultHasher`, whose output may change between Rust releases,
+/// so the same URLs stay selected across restarts and upgrades.
+fn is_sampled(url: &str, percent: f64) -> bool {
+    let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    // Buckets of 0.01% so fractional percentages are honored
+    ((hash % 10_000) as f64) < percent * 100.0
+}
+
+/// Reads an optional boolean environment variable, defaulting to false.
+fn env_flag(name: &'static str) -> Result<bool, Box<dyn std::error::Error>> {
+    match provenance::var(name) {
+        Ok(value) => match value.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" | "" => Ok(false),
+            _ => Err(format!("{} must be true or false", name).into()),
+        },
+        Err(_) => Ok(false),
+    }
+}
+
+/// Maps each monitored host name to the DNS server it is resolved through.
+///
+/// The resolver only sees host names, so URLs sharing a host have to agree on `dns_server`.
+fn dns_servers(
+    default: Option<SocketAddr>,
+    urls: &[UrlSpec],
+) -> Result<HashMap<String, SocketAddr>, Box<dyn std::error::Error>> {
+    let mut hosts: HashMap<String, Option<SocketAddr>> = HashMap::new();
+    for spec in urls {
+        let server = spec.dns_server.or(default);
+        for spec_url in spec.request_urls() {
+            // IP literals have nothing to resolve
+            let Some(host) = Url::parse(spec_url).ok().and_then(|url| match url.host() {
+                Some(url::Host::Domain(host)) => Some(host.to_lowercase()),
+                _ => None,
+            }) else {
+                continue;
+            };
+            if *hosts.entry(host.clone()).or_insert(server) != server {
+                return Err(format!("URLs on {} disagree on dns_server; use the same server for all of them", host).into());
+            }
+        }
+    }
+
+    Ok(hosts
+        .into_iter()
+        .filter_map(|(host, server)| Some((host, server?)))
+        .collect())
+}
+
+/// SOCKS5 proxy that HTTP checks of the selected origins are routed through.
+#[derive(Debug, Clone)]
+struct SocksProxy {
+    url: Url,
+    /// `scheme://host:port` of every URL that goes through the proxy.
+    origins: HashSet<String>,
+}
+
+impl SocksProxy {
+    /// Parses `socks5://[user:password@]host:port` (or `socks5h://` to resolve names through the
+    /// proxy) and collects the origins of the URLs that use it.
+    ///
+    /// The proxy is chosen per origin, so URLs sharing one have to agree on `socks_proxy`.
+    fn new(value: &str, urls: &[UrlSpec]) -> Result<Self, Box<dyn std::error::Error>> {
+        let url = Url::parse(value)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "socks5" | "socks5h") && url.host_str().is_some())
+            .ok_or("UPNOTIF_SOCKS_PROXY must be a socks5:// or socks5h:// URL")?;
+
+        let mut origins = HashSet::new();
+        let mut direct = HashSet::new();
+        for spec in urls {
+            for spec_url in spec.request_urls() {
+                let Some(origin) = http_url(spec_url).map(|url| url.origin().ascii_serialization()) else {
+                    continue;
+                };
+                if spec.socks_proxy {
+                    origins.insert(origin);
+                } else {
+                    direct.insert(origin);
+                }
+            }
+        }
+        if let Some(origin) = origins.intersection(&direct).next() {
+            return Err(format!("URLs on {} disagree on socks_proxy; use the same setting for all of them", origin).into());
+        }
+
+        Ok(Self { url, origins })
+    }
+
+    fn proxy(&self) -> Proxy {
+        let proxy = self.clone();
+        Proxy::custom(move |url| {
+            proxy
+                .origins
+                .contains(&url.origin().ascii_serialization())
+                .then(|| proxy.url.clone())
+        })
+    }
+
+    /// The proxy address without credentials, for logging.
+    fn display(&self) -> String {
+        format!(
+            "{}://{}:{}",
+            self.url.scheme(),
+            self.url.host_str().unwrap_or_default(),
+            self.url.port().unwrap_or(1080)
+        )
+    }
+}
+
+struct UrlMonitor {
+    client: Client,
+    /// Used for service paths that expect a redirect status rather than its target.
+    no_redirect_client: Client,
+    /// HTTP/1-only counterparts of `client` and `no_redirect_client` for URLs with `http_1_0`.
+    http1_client: Client,
+    http1_no_redirect_client: Client,
+    /// Opens connections for checks that don't go through reqwest, such as gRPC and `tcp_check`.
+    dialer: tls::Dialer,
+    notifier: Notifier,
+    /// One notifier per entry of `config.routes`, in the same order.
+    route_notifiers: Vec<Notifier>,
+    /// Whether each route's schedule was active at the last cycle, to notice on-call handoffs.
+    active_routes: Vec<bool>,
+    event_webhook: Option<EventWebhook>,
+    event_socket: Option<EventSocket>,
+    flags: Option<FlagSource>,
+    metrics: Option<Metrics>,
+    tracer: Option<Tracer>,
+    /// Deploy windows opened through the control endpoint; empty without one.
+    deploy_windows: DeployWindows,
+    /// URLs whose deploy window was active last cycle, to notice when it ends.
+    in_deploy_window: HashSet<String>,
+    config: Config,
+    states: HashMap<String, UrlState>,
+    /// Whether `states` was loaded from the state file rather than starting empty.
+    restored: bool,
+    /// Set after a failed state file write so the failure is only notified once.
+    state_write_failed: bool,
+    /// Set after a failed queue file write so the failure is only logged once.
+    queue_write_failed: bool,
+    pending_changes: Vec<Change>,
+    undelivered: VecDeque<(Destination, Notification)>,
+    dropped_undelivered: usize,
+    /// Text and send time of the last message delivered to each destination, used to drop accidental repeats.
+    last_sent: HashMap<Destination, (String, Instant)>,
+    cycle: u64,
+    readiness_results: HashMap<String, CheckResult>,
+    /// URLs that asked for a pause via `Retry-After`, with the time checks may resume.
+    backoff_until: HashMap<String, Instant>,
+    /// Most recently resolved addresses of each host with DNS tracking enabled.
+    dns_records: HashMap<String, BTreeSet<IpAddr>>,
+    /// Checks since the last digest, per URL.
+    digest_windows: HashMap<String, DigestWindow>,
+    /// Unix time the current digest window started.
+    digest_started: u64,
+    /// Checks of all URLs since the last activity summary.
+    activity_window: ActivityWindow,
+    /// Whether the last check cycle took longer than the interval, so the warning is logged once.
+    overrunning: bool,
+    /// Whether the last check of `UPNOTIF_CANARY_URL` failed, which pauses the other checks.
+    canary_down: bool,
+    /// URLs paused or resumed by their feature flag, announced with the next cycle's changes.
+    pause_changes: Vec<Change>,
+    /// Recent successful response times of URLs in a `compare_latency` pair or with `latency_consistency`, newest last.
+    latency_windows: HashMap<String, VecDeque<Duration>>,
+    /// URLs currently reported as too slow compared with their `compare_latency` reference.
+    slower_than_reference: HashSet<String>,
+    /// URLs currently reported as having erratic response times.
+    erratic_latency: HashSet<String>,
+}
+
+impl UrlMonitor {
+    fn new(config: Config) -> Self {
+        let resolver = dns::Resolver::new(config.dns_servers.clone(), config.connect_timeout);
+        let check_client = |follow_redirects: bool, http1_only: bool| {
+            let mut client = Client::builder()
+                .timeout(config.request_timeout)
+                .connect_timeout(config.connect_timeout)
+                .local_address(config.local_address);
+            if !follow_redirects {
+                client = client.redirect(reqwest::redirect::Policy::none());
+            } else if config.detect_https_downgrade {
+                client = client.redirect(downgrade_detecting_policy());
+            }
+            if let (true, Some(every)) = (follow_redirects, config.warmup_interval) {
+                // Keep warmed connections around at least until the next warmup
+                client = client.pool_idle_timeout(every + WARMUP_IDLE_MARGIN);
+            }
+            if http1_only {
+                // An HTTP/1.0 request would otherwise still go out as HTTP/2 when TLS negotiates it
+                client = client.http1_only();
+            }
+            if let Some(socks_proxy) = &config.socks_proxy {
+                // Only monitored origins are proxied; notifications and events go out directly
+                client = client.proxy(socks_proxy.proxy());
+            }
+            if resolver.is_custom() {
+                // Hosts without a configured server, such as the Slack webhook, still use the system resolver
+                client = client.dns_resolver(Arc::new(resolver.clone()));
+            }
+            client.build().expect("Failed to create HTTP client")
+        };
+        let client = check_client(true, false);
+        let no_redirect_client = check_client(false, false);
+        let http1_client = check_client(true, true);
+        let http1_no_redirect_client = check_client(false, true);
+        if let Some(socks_proxy) = &config.socks_proxy {
+            info!(
+                "Routing checks of {} origin(s) through SOCKS proxy {}",
+                socks_proxy.origins.len(),
+                socks_proxy.display()
+            );
+        }
+        if resolver.is_custom() {
+            let servers: BTreeSet<&SocketAddr> = config.dns_servers.values().collect();
+            info!(
+                "Resolving {} monitored host(s) through DNS server(s) {}",
+                config.dns_servers.len(),
+                servers.iter().map(|server| server.to_string()).collect::<Vec<_>>().join(", ")
+            );
+        }
+        let dialer = tls::Dialer {
+            connect_timeout: config.connect_timeout,
+            local_address: config.local_address,
+            resolver,
+        };
+
+        let slack_notifier = |webhook: &str| {
+            if config.test_mode {
+                Notifier::Console
+            } else {
+                Notifier::Slack {
+                    webhook: webhook.to_string(),
+                    colors: config.slack_colors.clone(),
+                    throttle: config.slack_rate_limit.map(Throttle::new),
+                }
+            }
+        };
+        let notifier = slack_notifier(&config.slack_webhook);
+        let route_notifiers = config
+            .routes
+            .iter()
+            .map(|route| match &route.routing_key {
+                Some(_) if config.test_mode => Notifier::Console,
+                Some(routing_key) => Notifier::PagerDuty {
+                    routing_key: routing_key.clone(),
+                },
+                None => slack_notifier(route.webhook.as_deref().unwrap_or_default()),
+            })
+            .collect();
+        let active_routes = config.routes.iter().map(|route| route.is_active(unix_now(), config.display_timezone)).collect();
+        let event_webhook = config
+            .event_webhook
+            .clone()
+            .map(|url| EventWebhook::new(url, config.event_rate_limit.map(Throttle::new)));
+        let event_socket = config.event_socket.as_deref().map(|path| {
+            EventSocket::bind(path).unwrap_or_else(|e| {
+                error!("Cannot listen for event consumers on {}: {}", path.display(), e);
+                std::process::exit(1);
+            })
+        });
+        let metrics = config.metrics_address.map(|address| {
+            let metrics = Metrics::new(config.metrics_buckets.clone());
+            if let Err(e) = metrics.serve(address) {
+                error!("Cannot serve metrics on {}: {}", address, e);
+                std::process::exit(1);
+            }
+            info!("Serving Prometheus metrics on http://{}/metrics", address);
+            metrics
+        });
+        let deploy_windows = DeployWindows::default();
+        if let Some(address) = config.control_address {
+            let targets = config
+                .urls
+                .iter()
+                .map(|spec| control::Target {
+                    url: spec.url.clone(),
+                    name: spec.name.clone(),
+                })
+                .collect();
+            if let Err(e) = deploy_windows.serve(address, config.control_token.clone(), targets) {
+                error!("Cannot serve the control endpoint on {}: {}", address, e);
+                std::process::exit(1);
+            }
+            info!("Serving the deploy window control endpoint on http://{}/deploy-windows", address);
+        }
+        let tracer = config.otlp.clone().map(|otlp| Tracer::start(otlp, Client::new()));
+        let flags = config
+            .flag_url
+            .clone()
+            .map(|url| FlagSource::new(url, config.flag_refresh));
+        let (states, digest) = restore_states(&config);
+        let digest_started = digest.as_ref().map_or_else(unix_now, |digest| digest.started_at);
+        let digest_windows = digest.map(|digest| digest.urls.into_iter().collect()).unwrap_or_default();
+        let (undelivered, dropped_undelivered) = restore_undelivered(&config);
+        let restored = !states.is_empty();
+
+        Self {
+            client,
+            no_redirect_client,
+            http1_client,
+            http1_no_redirect_client,
+            dialer,
+            notifier,
+            route_notifiers,
+            active_routes,
+            event_webhook,
+            event_socket,
+            flags,
+            metrics,
+            tracer,
+            deploy_windows,
+            in_deploy_window: HashSet::new(),
+            config,
+            states,
+            restored,
+            state_write_failed: false,
+            queue_write_failed: false,
+            pending_changes: Vec::new(),
+            undelivered,
+            dropped_undelivered,
+            last_sent: HashMap::new(),
+            cycle: 0,
+            readiness_results: HashMap::new(),
+            backoff_until: HashMap::new(),
+            dns_records: HashMap::new(),
+            digest_windows,
+            digest_started,
+            activity_window: ActivityWindow::default(),
+            overrunning: false,
+            canary_down: false,
+            pause_changes: Vec::new(),
+            latency_windows: HashMap::new(),
+            slower_than_reference: HashSet::new(),
+            erratic_latency: HashSet::new(),
+        }
+    }
+
+    /// Checks a URL, and with tracing enabled runs the check in a `CheckTrace` scope so its requests
+    /// carry the trace context, then records the check as a span.
+    async fn check_url_status(&self, spec: &UrlSpec) -> CheckResult {
+        let Some(tracer) = &self.tracer else {
+            return self.check_with_retries(spec).await;
+        };
+
+        let start = SystemTime::now();
+        let (result, trace) = CheckTrace::scope(self.check_with_retries(spec)).await;
+        let mut attributes = vec![
+            ("url.full", spec.url.as_str().into()),
+            ("http.request.method", spec.method.as_str().into()),
+            ("upnotif.status", result.status.to_string().into()),
+        ];
+        if let Some(name) = &spec.name {
+            attributes.push(("upnotif.name", name.as_str().into()));
+        }
+        if let Some(code) = result.status_code {
+            attributes.push(("http.response.status_code", i64::from(code).into()));
+        }
+        if let Some(latency) = result.latency {
+            attributes.push(("upnotif.latency_ms", (latency.as_millis() as i64).into()));
+        }
+        if let Some(ttfb) = result.ttfb {
+            attributes.push(("upnotif.ttfb_ms", (ttfb.as_millis() as i64).into()));
+        }
+        if let Some(category) = result.error_category {
+            attributes.push(("error.type", category.to_string().into()));
+        }
+        if let Some(id) = trace.request_id() {
+            attributes.push(("upnotif.request_id", id.into()));
+        }
+        tracer.record(Span {
+            context: trace.context,
+            name: format!("check {}", spec.method),
+            start,
+            end: SystemTime::now(),
+            attributes,
+            error: (result.status == UrlStatus::Down).then(|| result.reason.clone().unwrap_or_default()),
+        });
+        result
+    }
+
+    /// Checks a URL, retrying failures as configured.
+    async fn check_with_retries(&self, spec: &UrlSpec) -> CheckResult {
+        let attempts = if spec.retryable() {
+            1 + self.config.retries
+        } else {
+            1
+        };
+
+        let mut attempt = 1;
+        loop {
+            // Applied before deciding on a retry, since a response over `down_latency_ms` is a failure
+            let result = spec.apply_latency_threshold(self.check_sampled(spec).await);
+            if result.status.is_available() || attempt >= attempts {
+                return self.apply_ocsp_check(spec, result).await;
+            }
+
+            info!("Check of {} failed (attempt {}/{}), retrying", spec.url, attempt, attempts);
+            sleep(self.config.retry_delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// With `check_ocsp_stapling`, marks an available URL DEGRADED when its stapled OCSP response
+    /// is missing or unusable, and DOWN when it says the certificate was revoked.
+    async fn apply_ocsp_check(&self, spec: &UrlSpec, result: CheckResult) -> CheckResult {
+        if !spec.check_ocsp_stapling || !result.status.is_available() {
+            return result;
+        }
+        let Some((host, port)) = Url::parse(&spec.url)
+            .ok()
+            .and_then(|url| Some((url.host_str()?.to_string(), url.port().unwrap_or(443))))
+        else {
+            return result;
+        };
+
+        let warning = match ocsp::check(&host, port, &self.dialer).await {
+            Ok(Stapling::Good) => return result,
+            Ok(Stapling::Revoked) => {
+                return CheckResult {
+                    error_category: Some(FailureCategory::Tls),
+                    ..CheckResult::down(Some("certificate revoked according to its stapled OCSP response".to_string()))
+                }
+            }
+            Ok(Stapling::Missing) => "no stapled OCSP response".to_string(),
+            Ok(Stapling::Unusable(reason)) => reason,
+            Err(reason) => format!("OCSP stapling check failed: {}", reason),
+        };
+        match result.status {
+            // A slow response already explains the degradation
+            UrlStatus::Degraded => result,
+            _ => CheckResult {
+                status: UrlStatus::Degraded,
+                reason: Some(warning),
+                ..result
+            },
+        }
+    }
+
+    /// Checks a URL `samples` times in a row and resolves mixed outcomes with its sample policy.
+    async fn check_sampled(&self, spec: &UrlSpec) -> CheckResult {
+        if spec.samples <= 1 {
+            return self.check_once(spec).await;
+        }
+
+        let mut succeeded = 0;
+        let mut last_success = None;
+        let mut last_failure = None;
+        for _ in 0..spec.samples {
+            let result = self.check_once(spec).await;
+            if result.status.is_available() {
+                succeeded += 1;
+                last_success = Some(result);
+            } else {
+                last_failure = Some(result);
+            }
+        }
+
+        let summary = format!("{}/{} samples succeeded", succeeded, spec.samples);
+        match (last_success, last_failure) {
+            (Some(success), _) if spec.sample_policy.passes(succeeded, spec.samples) => success,
+            (_, Some(failure)) => CheckResult {
+                reason: Some(match failure.reason {
+                    Some(reason) => format!("{}; {}", summary, reason),
+                    None => summary,
+                }),
+                ..failure
+            },
+            (Some(success), None) => success,
+            (None, None) => unreachable!("at least one sample is taken"),
+        }
+    }
+
+    async fn check_once(&self, spec: &UrlSpec) -> CheckResult {
+        let started = Instant::now();
+
+        if !spec.cert_fingerprints.is_empty() {
+            if let Err(reason) = check_pinned_certificate(spec, &self.dialer).await {
+                return CheckResult::down(Some(reason));
+            }
+        }
+
+        if let Ok(url) = Url::parse(&spec.url) {
+            if matches!(url.scheme(), "grpc" | "grpcs") {
+                let result = match timeout(self.config.request_timeout, grpc::check(&url, &self.dialer)).await {
+                    Ok(Ok(())) => CheckResult::up(),
+                    Ok(Err(reason)) => CheckResult::down(Some(reason)),
+                    Err(_) => {
+                        return CheckResult {
+                            error_category: Some(FailureCategory::Timeout),
+                            ..CheckResult::down(Some("gRPC health check timed out".to_string()))
+                        }
+                    }
+                };
+                return result.with_latency(started.elapsed());
+            }
+        }
+
+        if !spec.paths.is_empty() {
+            return self.check_service_paths(spec).await.with_latency(started.elapsed());
+        }
+        if !spec.regions.is_empty() {
+            return self.check_regions(spec).await.with_latency(started.elapsed());
+        }
+
+        let client = self.check_client(spec, spec.expect_redirect.is_none());
+        let url = match Url::parse(&spec.url) {
+            Ok(url) => spec.cache_busted(url),
+            Err(e) => return CheckResult::down(Some(format!("invalid URL: {}", e))),
+        };
+        let (request, request_id) = self.tag_request(client.request(spec.method.clone(), url.clone()), url.as_str());
+        let request = spec.apply_http_version(spec.apply_negotiation_headers(request));
+        let mut request = spec.apply_body(request);
+        if let Some(signer) = &spec.signer {
+            request = signer.sign(request, &spec.method, &url, spec.body.as_deref());
+        }
+        let mut request = match spec.authorize(&self.client, request).await {
+            Ok(request) => request,
+            Err(reason) => return CheckResult::down(Some(reason)),
+        };
+        if spec.watch_changes {
+            if let Some(validators) = self.states.get(&spec.url).and_then(|state| state.validators.as_ref()) {
+                request = validators.apply(request);
+            }
+        }
+
+        // The TCP probe runs alongside the request so a failure can be attributed to a layer
+        let tcp_probe = async {
+            if spec.tcp_check {
+                Some(tcp_probe(&spec.url, &self.dialer).await)
+            } else {
+                None
+            }
+        };
+        let send = async {
+            let response = request.send().await;
+            (response, started.elapsed())
+        };
+        let ((response, ttfb), tcp) = tokio::join!(send, tcp_probe);
+        let ttfb = (spec.measure_ttfb && response.is_ok()).then_some(ttfb);
+        let status_code = response.as_ref().ok().map(|response| response.status().as_u16());
+        if let Ok(response) = &response {
+            spec.note_status(response.status()).await;
+        }
+        let remote_addr = response.as_ref().ok().and_then(|response| response.remote_addr());
+        let validators = response
+            .as_ref()
+            .ok()
+            .filter(|response| spec.watch_changes && response.status().is_success())
+            .and_then(Validators::from_response);
+
+        let result = match response {
+            // The content is unchanged since the last full response, which already passed its checks
+            Ok(response) if spec.watch_changes && response.status() == StatusCode::NOT_MODIFIED => {
+                CheckResult::up().with_latency(started.elapsed())
+            }
+            // A server bug that would otherwise pass or fail depending on the redirect expectations
+            Ok(response) if malformed_redirect(&response).is_some() => {
+                CheckResult::down(malformed_redirect(&response)).with_latency(started.elapsed())
+            }
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = self
+                    .config
+                    .honor_retry_after
+                    .then(|| retry_after(&response))
+                    .flatten();
+                CheckResult::rate_limited(self.config.throttled_status, retry_after)
+                    .with_latency(started.elapsed())
+            }
+            Ok(response) if spec.expect_redirect.is_some() => {
+                match spec.check_redirect(&response).and_then(|()| check_cookies(spec, &response)) {
+                    Ok(warning) => CheckResult::up().with_warning(warning),
+                    Err(reason) => CheckResult::down(Some(reason)),
+                }
+            }
+            .with_latency(started.elapsed()),
+            // The module decides on any status, so it can accept a 404 or reject a 200
+            Ok(response) if spec.wasm_check.is_some() => self.check_with_wasm(spec, response, started).await,
+            Ok(response) if !response.status().is_success() => {
+                CheckResult::down(None).with_latency(started.elapsed())
+            }
+            Ok(response) if self.config.detect_cross_host_redirects => {
+                match spec.unexpected_redirect_host(&response) {
+                    Some(host) => CheckResult::down(Some(format!("redirected to another host: {}", host)))
+                        .with_latency(started.elapsed()),
+                    None => self.check_response_body(spec, response, started).await,
+                }
+            }
+            Ok(response) => self.check_response_body(spec, response, started).await,
+            Err(e) => CheckResult {
+                error_category: Some(classify_error(&e)),
+                ..CheckResult::down(https_downgrade(&e).map(|downgrade| downgrade.to_string()))
+            },
+        };
+
+        // Only validators of a response that passed its checks may be used to skip them later
+        let validators = validators.filter(|_| result.status.is_available());
+        let reason = match (&result.status, tcp) {
+            (UrlStatus::Down, Some(tcp)) => Some(describe_layers(tcp, status_code, result.reason.as_deref())),
+            _ => result.reason.clone(),
+        };
+        CheckResult {
+            status_code,
+            validators,
+            reason,
+            remote_addr,
+            ttfb,
+            ..result
+        }
+        .with_request_id(self.alert_request_id(request_id.as_deref()))
+    }
+
+    /// Final step of a check for a successful response: validates headers and cookies and, if any body checks are set,
+    /// the body.
+    async fn check_response_body(&self, spec: &UrlSpec, response: reqwest::Response, started: Instant) -> CheckResult {
+        if let Err(reason) = check_headers(spec, &response) {
+            return CheckResult::down(Some(reason)).with_latency(started.elapsed());
+        }
+        let warning = match check_cookies(spec, &response) {
+            Ok(warning) => warning,
+            Err(reason) => return CheckResult::down(Some(reason)).with_latency(started.elapsed()),
+        };
+        self.check_body(spec, response, started).await.with_warning(warning)
+    }
+
+    /// Hands the response to the URL's WASM module, which decides its status. Only the request and the
+    /// body transfer count toward the response time, not the module.
+    async fn check_with_wasm(&self, spec: &UrlSpec, response: reqwest::Response, started: Instant) -> CheckResult {
+        let Some(wasm) = spec.wasm_check.clone() else {
+            return CheckResult::up().with_latency(started.elapsed());
+        };
+        let status = response.status().as_u16();
+        let mut headers: BTreeMap<String, String> = BTreeMap::new();
+        for (name, value) in response.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.to_string());
+        }
+        let body = match response.bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                return CheckResult::down(Some(format!("failed to read body: {}", e))).with_latency(started.elapsed())
+            }
+        };
+        let latency = started.elapsed();
+
+        let url = spec.url.clone();
+        let module = wasm.path().display().to_string();
+        // A module may run for a while before its fuel runs out, which must not stall other checks
+        let verdict = tokio::task::spawn_blocking(move || {
+            wasm.run(&WasmInput {
+                version: INTERFACE_VERSION,
+                url: &url,
+                status,
+                headers,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            })
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        let result = match verdict {
+            Ok(WasmVerdict { status: WasmStatus::Up, .. }) => CheckResult::up(),
+            Ok(WasmVerdict { status: WasmStatus::Degraded, reason }) => CheckResult {
+                status: UrlStatus::Degraded,
+                reason,
+                ..CheckResult::up()
+            },
+            Ok(WasmVerdict { status: WasmStatus::Down, reason }) => CheckResult::down(reason),
+            Err(e) => CheckResult::down(Some(format!("WASM check {} failed: {}", module, e))),
+        };
+        result.with_latency(latency)
+    }
+
+    /// Checks the body of a response whose headers passed, when the URL has any body checks.
+    async fn check_body(&self, spec: &UrlSpec, response: reqwest::Response, started: Instant) -> CheckResult {
+        let checks_json = spec.expect_json || spec.json_schema.is_some() || !spec.assertions.is_empty();
+        let checks_body = checks_json
+            || !spec.must_contain.is_empty()
+            || !spec.must_not_contain.is_empty()
+            || spec.min_body_bytes.is_some()
+            || spec.stuck_after.is_some();
+        // Without body checks the body is only read to time its transfer
+        if !checks_body && !spec.measure_ttfb {
+            return CheckResult::up().with_latency(started.elapsed());
+        }
+
+        // Body checks can't pass without a body, which a 204 never has
+        if checks_body && response.status() == StatusCode::NO_CONTENT {
+            let result = match self.config.no_content_status {
+                NoContentStatus::Up => CheckResult::up(),
+                NoContentStatus::Fail => {
+                    CheckResult::down(Some("204 No Content, but the body is checked".to_string()))
+                }
+            };
+            return result.with_latency(started.elapsed());
+        }
+
+        let charset = response_charset(&response);
+        let body = match response.bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                return CheckResult::down(Some(format!("failed to read body: {}", e))).with_latency(started.elapsed())
+            }
+        };
+
+        if let Some(min) = spec.min_body_bytes.filter(|min| body.len() < *min) {
+            return CheckResult::down(Some(format!("body too small: {} bytes, expected at least {}", body.len(), min)))
+                .with_latency(started.elapsed());
+        }
+
+        let result = match check_content(spec, &String::from_utf8_lossy(&body)) {
+            Err(reason) => CheckResult::down(Some(reason)),
+            Ok(()) if checks_json => check_json_body(spec, charset, &body),
+            Ok(()) => CheckResult::up(),
+        };
+        let body_hash = spec.stuck_after.map(|_| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            body.hash(&mut hasher);
+            hasher.finish()
+        });
+        CheckResult { body_hash, ..result }.with_latency(started.elapsed())
+    }
+
+    /// The client for a check request of `spec`, HTTP/1-only for `http_1_0`.
+    fn check_client(&self, spec: &UrlSpec, follow_redirects: bool) -> &Client {
+        match (spec.http_1_0, follow_redirects) {
+            (false, true) => &self.client,
+            (false, false) => &self.no_redirect_client,
+            (true, true) => &self.http1_client,
+            (true, false) => &self.http1_no_redirect_client,
+        }
+    }
+
+    /// Adds the trace context of a traced check and, when configured, a fresh correlation ID header to a check request.
+    fn tag_request(&self, request: RequestBuilder, url: &str) -> (RequestBuilder, Option<String>) {
+        let trace = CheckTrace::current();
+        let request = match &trace {
+            Some(trace) => request.header("traceparent", trace.context.traceparent()),
+            None => request,
+        };
+        match &self.config.request_id_header {
+            Some(header) => {
+                let id = Uuid::new_v4().to_string();
+                debug!("Checking {} with {}: {}", url, header, id);
+                if let Some(trace) = &trace {
+                    trace.set_request_id(&id);
+                }
+                (request.header(header.clone(), id.as_str()), Some(id))
+            }
+            None => (request, None),
+        }
+    }
+
+    /// The request ID to mention in a down alert, if alerts should include it.
+    fn alert_request_id<'a>(&self, request_id: Option<&'a str>) -> Option<&'a str> {
+        request_id.filter(|_| self.config.request_id_in_alerts)
+    }
+
+    /// Checks every path of a service; the service is up only if all paths meet their expectation.
+    async fn check_service_paths(&self, spec: &UrlSpec) -> CheckResult {
+        let Ok(base) = Url::parse(&spec.url) else {
+            return CheckResult::down(None);
+        };
+
+        let mut failures = Vec::new();
+
+        for check in &spec.paths {
+            let Ok(url) = base.join(&check.path) else {
+                failures.push(format!("{}: invalid path", check.path));
+                continue;
+            };
+
+            let follow_redirects = !check.expect_status.as_ref().is_some_and(|expected| expected.includes_redirect());
+            let client = self.check_client(spec, follow_redirects);
+
+            let url = spec.cache_busted(url);
+            let (request, request_id) = self.tag_request(client.get(url.clone()), url.as_str());
+            let mut request = spec.apply_http_version(spec.apply_negotiation_headers(request));
+            if let Some(signer) = &spec.signer {
+                request = signer.sign(request, &Method::GET, &url, None);
+            }
+            let request = match spec.authorize(&self.client, request).await {
+                Ok(request) => request,
+                Err(reason) => {
+                    failures.push(format!("{}: {}", check.path, reason));
+                    continue;
+                }
+            };
+            let id_suffix = self
+                .alert_request_id(request_id.as_deref())
+                .map(|id| format!(" (request id {})", id))
+                .unwrap_or_default();
+
+            let code = match request.send().await {
+                Ok(response) => {
+                    spec.note_status(response.status()).await;
+                    if let Some(reason) = malformed_redirect(&response) {
+                        failures.push(format!("{}: {}{}", check.path, reason, id_suffix));
+                        continue;
+                    }
+                    response.status().as_u16()
+                }
+                Err(_) => {
+                    failures.push(format!("{}: request failed{}", check.path, id_suffix));
+                    continue;
+                }
+            };
+
+            let ok = match &check.expect_status {
+                Some(expected) => expected.contains(code),
+                None => (200..300).contains(&code),
+            };
+            if !ok {
+                let expected = check
+                    .expect_status
+                    .as_ref()
+                    .map_or_else(|| "2xx".to_string(), StatusCodes::to_string);
+                failures.push(format!(
+                    "{} returned {} (expected {}){}",
+                    check.path, code, expected, id_suffix
+                ));
+            }
+        }
+
+        if failures.is_empty() {
+            CheckResult::up()
+        } else {
+            CheckResult::down(Some(failures.join("; ")))
+        }
+    }
+
+    /// Checks every region of a service at once. The service is DOWN when all regions are down and
+    /// DEGRADED when only some are, or when the regions disagree on `region_compare`.
+    async fn check_regions(&self, spec: &UrlSpec) -> CheckResult {
+        let checks = spec.regions.iter().map(|(region, url)| async move {
+            (region.as_str(), self.check_region(spec, url).await)
+        });
+        let outcomes = join_all(checks).await;
+
+        let failures: Vec<String> = outcomes
+            .iter()
+            .filter_map(|(region, outcome)| outcome.as_ref().err().map(|reason| format!("{}: {}", region, reason)))
+            .collect();
+        if failures.len() == outcomes.len() {
+            return CheckResult::down(Some(failures.join("; ")));
+        }
+
+        let mut problems = Vec::new();
+        if !failures.is_empty() {
+            problems.push(format!(
+                "{} of {} regions down: {}",
+                failures.len(),
+                outcomes.len(),
+                failures.join("; ")
+            ));
+        }
+
+        // Only regions that answered take part in the comparison
+        let values: Vec<(&str, &str)> = outcomes
+            .iter()
+            .filter_map(|(region, outcome)| Some((*region, outcome.as_ref().ok()?.as_deref()?)))
+            .collect();
+        if let (Some(compare), Some((_, first))) = (&spec.region_compare, values.first()) {
+            if values.iter().any(|(_, value)| value != first) {
+                let listed: Vec<String> = values.iter().map(|(region, value)| format!("{}={}", region, value)).collect();
+                problems.push(format!("regions disagree on {}: {}", compare, listed.join(", ")));
+            }
+        }
+
+        if problems.is_empty() {
+            CheckResult::up()
+        } else {
+            CheckResult {
+                status: UrlStatus::Degraded,
+                reason: Some(problems.join("; ")),
+                ..CheckResult::up()
+            }
+        }
+    }
+
+    /// Checks one regional endpoint, returning the value of `region_compare` if it is set.
+    async fn check_region(&self, spec: &UrlSpec, url: &Url) -> Result<Option<String>, String> {
+        let client = self.check_client(spec, true);
+        let url = &spec.cache_busted(url.clone());
+        let (request, request_id) = self.tag_request(client.request(spec.method.clone(), url.clone()), url.as_str());
+        let request = spec.apply_http_version(spec.apply_negotiation_headers(request));
+        let mut request = spec.apply_body(request);
+        if let Some(signer) = &spec.signer {
+            request = signer.sign(request, &spec.method, url, spec.body.as_deref());
+        }
+        let request = spec.authorize(&self.client, request).await?;
+        let id_suffix = self
+            .alert_request_id(request_id.as_deref())
+            .map(|id| format!(" (request id {})", id))
+            .unwrap_or_default();
+
+        let response = request.send().await.map_err(|_| format!("request failed{}", id_suffix))?;
+        let status = response.status();
+        spec.note_status(status).await;
+        if let Some(reason) = malformed_redirect(&response) {
+            return Err(format!("{}{}", reason, id_suffix));
+        }
+        if !status.is_success() {
+            return Err(format!("returned {}{}", status.as_u16(), id_suffix));
+        }
+
+        let value = match &spec.region_compare {
+            None => return Ok(None),
+            Some(RegionCompare::Header(name)) => response
+                .headers()
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned()),
+            Some(RegionCompare::Json(path)) => {
+                let body = response.text().await.map_err(|_| format!("failed to read body{}", id_suffix))?;
+                let document: serde_json::Value =
+                    serde_json::from_str(&body).map_err(|_| format!("response is not valid JSON{}", id_suffix))?;
+                path.find(&document).map(|value| match value {
+                    serde_json::Value::String(text) => text.clone(),
+                    value => value.to_string(),
+                })
+            }
+        };
+        // A missing value counts as different from any present one
+        Ok(Some(value.unwrap_or_else(|| "missing".to_string())))
+    }
+
+    /// Formats a URL's status for a notification at the configured detail level; `changed` selects
+    /// the wording of a status change over that of the startup report.
+    fn status_line(&self, check: &UrlCheck, now: u64, changed: bool) -> String {
+        if self.config.detail_level == DetailLevel::Terse {
+            return format!("{} {}", check.display_name(), check.result.status);
+        }
+
+        let mut line = match (changed, &check.previous, &check.result.status, check.down_since) {
+            (true, Some(UrlStatus::Down), UrlStatus::Up, Some(down_since)) => format!(
+                "{} {} recovered (was down since {}, {} ago){}",
+                check.result.status.emoji(),
+                check.display_name(),
+                format_local_time(down_since, now, self.config.display_timezone),
+                format_elapsed(now.saturating_sub(down_since)),
+                self.tag_suffix(check)
+            ),
+            (true, ..) => format!(
+                "{} {} is now {}{}",
+                check.result.status.emoji(),
+                check.display_name(),
+                check.result,
+                self.tag_suffix(check)
+            ),
+            (false, ..) => format!(
+                "{} {} is {}{}",
+                check.result.status.emoji(),
+                check.display_name(),
+                check.result,
+                self.tag_suffix(check)
+            ),
+        };
+
+        if self.config.show_server_ip && check.result.status == UrlStatus::Down && is_http_url(&check.url) {
+            match check.result.remote_addr {
+                Some(address) => line.push_str(&format!(" · server {}", address.ip())),
+                None => line.push_str(" · server unknown, no HTTP response"),
+            }
+        }
+
+        if self.config.detail_level == DetailLevel::Verbose {
+            if let Some(latency) = check.result.latency {
+                line.push_str(&format!(" · {}ms", latency.as_millis()));
+            }
+            if let Some(code) = check.result.status_code {
+                line.push_str(&format!(" · HTTP {}", code));
+            }
+            line.push_str(&format!(" · {}", format_local_time(now, now, self.config.display_timezone)));
+        }
+        line
+    }
+
+    /// Whether a change of `check` may be notified; otherwise logs what suppressed it.
+    fn should_notify(&self, check: &UrlCheck, transition: Transition, now: u64, line: &str) -> bool {
+        if let Some(until) = self.deploy_windows.active(&check.url, now) {
+            info!("Notification suppressed by deploy window until {}: {}", format_utc(until), line);
+            return false;
+        }
+        if !rules::should_notify(&self.config.alert_rules, &check.tags, transition, now) {
+            info!("Notification suppressed by alert rule: {}", line);
+            return false;
+        }
+        true
+    }
+
+    fn tag_suffix(&self, check: &UrlCheck) -> String {
+        if self.config.show_tags {
+            check.tag_suffix()
+        } else {
+            String::new()
+        }
+    }
+
+    fn notifier(&self, destination: Destination) -> &Notifier {
+        match destination {
+            Destination::Default => &self.notifier,
+            Destination::Route(index) => &self.route_notifiers[index],
+        }
+    }
+
+    /// Sends a message unless the identical text was already sent within `UPNOTIF_DUPLICATE_WINDOW_SECONDS`.
+    async fn send_notification(
+        &mut self,
+        destination: Destination,
+        notification: &Notification,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some((text, sent_at)) = self.last_sent.get(&destination) {
+            if *text == notification.text && sent_at.elapsed() < self.config.duplicate_window {
+                info!(
+                    "Suppressed duplicate notification sent {}s ago: {}",
+                    sent_at.elapsed().as_secs(),
+                    notification.text
+                );
+                return Ok(());
+            }
+        }
+
+        self.notifier(destination).send(&self.client, notification).await?;
+        if !self.config.duplicate_window.is_zero() {
+            self.last_sent
+                .insert(destination, (notification.text.clone(), Instant::now()));
+        }
+        Ok(())
+    }
+
+    /// Sends a message, splitting or truncating it first if it is too long for the notifier.
+    async fn deliver(&mut self, destination: Destination, notification: Notification, description: &str) {
+        let parts = match self.config.max_message_len.or(self.notifier(destination).max_message_len()) {
+            Some(limit) => notification.fit(limit, self.config.message_overflow),
+            None => vec![notification],
+        };
+        for part in parts {
+            self.deliver_one(destination, part, description).await;
+        }
+    }
+
+    /// Sends a single message, queueing it for a later retry if the notifier is unavailable.
+    async fn deliver_one(&mut self, destination: Destination, notification: Notification, description: &str) {
+        if self.flush_undelivered().await {
+            match self.send_notification(destination, &notification).await {
+                Ok(()) => return,
+                Err(e) => {
+                    if self.config.test_mode {
+                        error!("Failed to log {}: {}", description, e);
+                    } else {
+                        error!("Failed to send {}: {}", description, e);
+                    }
+                }
+            }
+        }
+
+        if self.config.notify_queue_size == 0 {
+            return;
+        }
+
+        if self.undelivered.len() >= self.config.notify_queue_size {
+            self.undelivered.pop_front();
+            self.dropped_undelivered += 1;
+        }
+        self.undelivered.push_back((destination, notification));
+        self.persist_undelivered();
+    }
+
+    /// Retries queued messages in order, returning true once nothing is left undelivered.
+    async fn flush_undelivered(&mut self) -> bool {
+        let queued = self.undelivered.len();
+        while let Some((destination, notification)) = self.undelivered.front().cloned() {
+            if let Err(e) = self.send_notification(destination, &notification).await {
+                warn!(
+                    "Notifier still unavailable, {} notification(s) queued: {}",
+                    self.undelivered.len(),
+                    e
+                );
+                if self.undelivered.len() < queued {
+                    self.persist_undelivered();
+                }
+                return false;
+            }
+            self.undelivered.pop_front();
+        }
+
+        if self.dropped_undelivered > 0 {
+            let notification = Notification {
+                text: format!(
+                    "⚠️ {} notification(s) could not be delivered during a notifier outage and were dropped",
+                    self.dropped_undelivered
+                ),
+                severity: Severity::Warning,
+                mentions: Vec::new(),
+                incident: None,
+            };
+            if self.send_notification(Destination::Default, &notification).await.is_err() {
+                self.persist_undelivered();
+                return false;
+            }
+            self.dropped_undelivered = 0;
+            self.persist_undelivered();
+        } else if queued > 0 {
+            self.persist_undelivered();
+        }
+
+        true
+    }
+
+    /// Writes the undelivered queue to `UPNOTIF_NOTIFY_QUEUE_FILE`, if one is configured.
+    fn persist_undelivered(&mut self) {
+        let Some(path) = &self.config.notify_queue_file else {
+            return;
+        };
+
+        let outbox = PersistedOutbox {
+            version: OUTBOX_VERSION,
+            saved_at: unix_now(),
+            dropped: self.dropped_undelivered,
+            messages: self
+                .undelivered
+                .iter()
+                .map(|(destination, notification)| PersistedNotification {
+                    route: match destination {
+                        Destination::Default => None,
+                        Destination::Route(index) => Some(self.config.routes[*index].name.clone()),
+                    },
+                    text: notification.text.clone(),
+                    severity: notification.severity.to_string(),
+                    mentions: notification.mentions.iter().map(Mention::to_string).collect(),
+                    incident: notification.incident.as_ref().map(|incident| incident.key.clone()),
+                    resolved: notification.incident.as_ref().is_some_and(|incident| incident.resolved),
+                })
+                .collect(),
+        };
+
+        match outbox::save(path, &outbox) {
+            Ok(()) => {
+                if self.queue_write_failed {
+                    info!("Notification queue file {} written successfully again", path.display());
+                    self.queue_write_failed = false;
+                }
+            }
+            Err(e) => {
+                // The queue is still kept in memory, only a restart would lose it
+                if !self.queue_write_failed {
+                    warn!("Failed to persist undelivered notifications to {}: {}", path.display(), e);
+                    self.queue_write_failed = true;
+                }
+            }
+        }
+    }
+
+    async fn send_events(&self, checks: &[UrlCheck]) {
+        if self.event_webhook.is_none() && self.event_socket.is_none() {
+            return;
+        }
+
+        let timestamp = unix_now();
+
+        for check in checks {
+            let Some(previous) = &check.previous else {
+                continue;
+            };
+            if !check.status_changed() {
+                continue;
+            }
+
+            let event = StatusEvent {
+                url: check.url.clone(),
+                name: check.name.clone(),
+                tags: check.tags.clone(),
+                previous: previous.to_string(),
+                current: check.result.status.to_string(),
+                timestamp,
+                latency_ms: check.result.latency.map(|latency| latency.as_millis() as u64),
+            };
+
+            if let Some(event_socket) = &self.event_socket {
+                event_socket.publish(&event);
+            }
+            if let Some(event_webhook) = &self.event_webhook {
+                if let Err(e) = event_webhook.send(&self.client, &event).await {
+                    error!("Failed to send status event for {}: {}", check.url, e);
+                }
+            }
+        }
+    }
+
+    /// Checks every URL, running hosts in parallel and requests to the same host sequentially.
+    async fn check_hosts(&mut self) -> HashMap<String, CheckResult> {
+        let cycle = self.cycle;
+        self.cycle += 1;
+
+        self.refresh_flags().await;
+        let checked = self.run_host_checks(cycle).await;
+
+        checked
+            .into_iter()
+            .map(|(url, (primary, readiness))| {
+                if let Some(readiness) = readiness {
+                    self.readiness_results.insert(url.clone(), readiness);
+                }
+                let result = match self.readiness_results.get(&url) {
+                    Some(readiness) if primary.status.is_available() && readiness.status == UrlStatus::Down => {
+                        CheckResult {
+                            status: UrlStatus::Down,
+                            reason: Some(match &readiness.reason {
+                                Some(reason) => format!("readiness check failed: {}", reason),
+                                None => "readiness check failed".to_string(),
+                            }),
+                            ..primary
+                        }
+                    }
+                    _ => primary,
+                };
+                (url, result)
+            })
+            .collect()
+    }
+
+    /// Fetches feature flags when the refresh interval has passed, logging URLs that were turned on or off.
+    async fn refresh_flags(&mut self) {
+        let Some(flags) = &mut self.flags else {
+            return;
+        };
+        if !flags.is_due() {
+            return;
+        }
+
+        let names: BTreeSet<&str> = self.config.urls.iter().filter_map(|spec| spec.flag.as_deref()).collect();
+        match flags.refresh(&self.client, &names).await {
+            Ok(changed) => {
+                for (name, enabled) in changed {
+                    for spec in self.config.urls.iter().filter(|spec| spec.flag.as_deref() == Some(name.as_str())) {
+                        let action = if enabled { "Resuming" } else { "Pausing" };
+                        info!("{} checks of {}, flag {} is {}", action, spec.url, name, if enabled { "on" } else { "off" });
+                        if self.config.alert_on_pause_resume {
+                            let display_name = spec.name.as_deref().unwrap_or(&spec.url);
+                            let line = if enabled {
+                                format!("▶️ Resumed monitoring {}, flag {} is on", display_name, name)
+                            } else {
+                                format!("⏸️ Paused monitoring {}, flag {} is off", display_name, name)
+                            };
+                            self.pause_changes.push(Change {
+                                line,
+                                severity: Severity::Info,
+                                mentions: Vec::new(),
+                                tags: spec.tags.clone(),
+                                incident: None,
+                            });
+                        }
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to fetch feature flags from {}, keeping the last known flags: {}", url_host(flags.url()), e),
+        }
+    }
+
+    fn flag_enabled(&self, spec: &UrlSpec) -> bool {
+        match (&self.flags, &spec.flag) {
+            (Some(flags), Some(name)) => flags.is_enabled(name),
+            _ => true,
+        }
+    }
+
+    async fn run_host_checks(&self, cycle: u64) -> HashMap<String, (CheckResult, Option<CheckResult>)> {
+        let now = Instant::now();
+        let mut specs: Vec<&UrlSpec> = self.config.urls.iter().collect();
+        if self.config.shuffle_checks {
+            // Shuffles both the order hosts start in and the order of URLs on each host
+            specs.shuffle(&mut rand::thread_rng());
+        }
+
+        let mut groups: Vec<(String, Vec<&UrlSpec>)> = Vec::new();
+        for spec in specs {
+            if self.states.get(&spec.url).is_some_and(|state| state.auto_disabled) {
+                continue;
+            }
+            if !self.flag_enabled(spec) {
+                continue;
+            }
+            if self.backoff_until.get(&spec.url).is_some_and(|until| now < *until) {
+                info!("Skipping {}, backing off after Retry-After", spec.url);
+                continue;
+            }
+            let host = url_host(&spec.url);
+            match groups.iter_mut().find(|(group_host, _)| *group_host == host) {
+                Some((_, specs)) => specs.push(spec),
+                None => groups.push((host, vec![spec])),
+            }
+        }
+
+        let checks = groups.into_iter().map(|(host, specs)| async move {
+            let delay = self.config.delay_for_host(&host);
+            let mut results = Vec::with_capacity(specs.len());
+            for (index, spec) in specs.into_iter().enumerate() {
+                if index > 0 && !delay.is_zero() {
+                    sleep(delay).await;
+                }
+                let primary = self.check_url_status(spec).await;
+                let readiness = match &spec.readiness {
+                    Some(readiness) if readiness.is_due(cycle, &primary) => {
+                        if !delay.is_zero() {
+                            sleep(delay).await;
+                        }
+                        Some(self.check_url_status(&UrlSpec::plain(readiness.url.clone())).await)
+                    }
+                    _ => None,
+                };
+                results.push((spec.url.clone(), (primary, readiness)));
+            }
+            results
+        });
+
+        join_all(checks).await.into_iter().flatten().collect()
+    }
+
+    async fn check_all_urls(&mut self) -> Vec<UrlCheck> {
+        let checked = self.check_hosts().await;
+        self.write_csv_rows(&checked);
+
+        let now = Instant::now();
+        for (url, result) in &checked {
+            match result.retry_after {
+                Some(wait) => {
+                    self.backoff_until.insert(url.clone(), now + wait.min(MAX_RETRY_AFTER));
+                }
+                None => {
+                    self.backoff_until.remove(url);
+                }
+            }
+        }
+
+        self.record_results(checked)
+    }
+
+    /// Appends this cycle's raw results to the CSV log, in config order.
+    fn write_csv_rows(&self, checked: &HashMap<String, CheckResult>) {
+        let Some(csv_log) = &self.config.csv_log else {
+            return;
+        };
+
+        let timestamp = format_utc(unix_now());
+        let rows: Vec<CsvRow> = self
+            .config
+            .urls
+            .iter()
+            .filter_map(|spec| {
+                let result = checked.get(&spec.url)?;
+                Some(CsvRow {
+                    timestamp: timestamp.clone(),
+                    url: &spec.url,
+                    status: result.status.to_string(),
+                    latency_ms: result.latency.map(|latency| latency.as_millis() as u64),
+                    status_code: result.status_code,
+                })
+            })
+            .collect();
+
+        if let Err(e) = csv_log.append(&rows) {
+            warn!("Failed to append to CSV log {}: {}", csv_log.path().display(), e);
+        }
+    }
+
+    /// Stores the latest status of every URL and pairs each result with the previous status.
+    fn record_results(&mut self, mut checked: HashMap<String, CheckResult>) -> Vec<UrlCheck> {
+        let mut results = Vec::new();
+        let now = unix_now();
+
+        for spec in &self.config.urls {
+            // URLs backing off after a Retry-After keep their previous state
+            let Some(mut result) = checked.remove(&spec.url) else {
+                continue;
+            };
+
+            let state = self.states.entry(spec.url.clone()).or_insert_with(|| UrlState {
+                status: result.status.clone(),
+                reason: None,
+                latency: None,
+                last_checked: now,
+                checks: 0,
+                up_checks: 0,
+                latency_baseline: LatencyBaseline::default(),
+                consecutive_failures: 0,
+                failing_since: None,
+                recovering_since: None,
+                latency_anomalous: false,
+                gone_checks: 0,
+                auto_disabled: false,
+                validators: None,
+                last_body_hash: None,
+                identical_bodies: 0,
+                failure_category: None,
+                grouped_under: None,
+            });
+            let previous = (state.checks > 0).then(|| state.status.clone());
+
+            let down_since = state.failing_since;
+            let failed = result.status == UrlStatus::Down;
+            if failed {
+                state.consecutive_failures += 1;
+                state.failing_since.get_or_insert(now);
+                state.recovering_since = None;
+            } else {
+                state.consecutive_failures = 0;
+            }
+            let available = result.status.is_available();
+
+            let content_changed = matches!(
+                (&result.validators, &state.validators),
+                (Some(current), Some(last)) if current != last
+            );
+            if result.validators.is_some() {
+                state.validators = result.validators.clone();
+            }
+
+            // Only successful checks count towards a stuck body; the hash survives failures in between
+            if let (Some(after), Some(hash), true) = (spec.stuck_after, result.body_hash, available) {
+                if state.last_body_hash == Some(hash) {
+                    state.identical_bodies += 1;
+                } else {
+                    state.last_body_hash = Some(hash);
+                    state.identical_bodies = 1;
+                }
+                if state.identical_bodies >= after && result.status == UrlStatus::Up {
+                    result = CheckResult {
+                        status: UrlStatus::Degraded,
+                        reason: Some(format!(
+                            "response body unchanged for {} checks, backend may be stuck",
+                            state.identical_bodies
+                        )),
+                        ..result
+                    };
+                }
+            }
+
+            let mut auto_disabled = None;
+            match (self.config.auto_disable_after, result.status_code) {
+                (Some(after), Some(code)) if self.config.gone_status_codes.contains(&code) => {
+                    state.gone_checks += 1;
+                    if state.gone_checks >= after {
+                        state.auto_disabled = true;
+                        auto_disabled = Some(code);
+                    }
+                }
+                _ => state.gone_checks = 0,
+            }
+            if self.config.digest_interval.is_some() {
+                self.digest_windows
+                    .entry(spec.url.clone())
+                    .or_default()
+                    .record(available, result.latency, result.ttfb);
+            }
+            if self.config.activity_interval.is_some() {
+                let failure = (result.status == UrlStatus::Down).then(|| result.failure_category().to_string());
+                self.activity_window.record(failure, result.latency);
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.record(&spec.url, available, result.latency, result.ttfb);
+            }
+
+            // A new failure only changes the status once it is confirmed by enough consecutive
+            // failures, unless its status code is one that alerts immediately
+            let confirmed = result.status != UrlStatus::Down
+                || previous.as_ref().is_none_or(|status| *status == UrlStatus::Down)
+                || state.consecutive_failures >= self.config.failure_threshold
+                || result
+                    .status_code
+                    .is_some_and(|code| self.config.immediate_alert_codes.contains(&code));
+            let result = if confirmed {
+                result
+            } else {
+                info!(
+                    "{} failed ({}/{} before alerting): {}",
+                    spec.url, state.consecutive_failures, self.config.failure_threshold, result
+                );
+                CheckResult {
+                    status: state.status.clone(),
+                    reason: state.reason.clone(),
+                    ..result
+                }
+            };
+
+            // A DOWN URL stays DOWN until it has passed its checks for the recovery hold, so a
+            // brief recovery in the middle of an outage neither ends the outage nor alerts twice
+            let holding = match self.config.recovery_hold {
+                Some(hold) if !failed && state.status == UrlStatus::Down => {
+                    let elapsed = now.saturating_sub(*state.recovering_since.get_or_insert(now));
+                    (elapsed < hold.as_secs()).then_some((elapsed, hold))
+                }
+                _ => None,
+            };
+            let result = match holding {
+                Some((elapsed, hold)) => {
+                    info!(
+                        "{} is {} again, holding its recovery ({}s of {}s)",
+                        spec.url,
+                        result.status,
+                        elapsed,
+                        hold.as_secs()
+                    );
+                    CheckResult {
+                        status: state.status.clone(),
+                        reason: state.reason.clone(),
+                        ..result
+                    }
+                }
+                None => result,
+            };
+            if !failed && holding.is_none() {
+                state.failing_since = None;
+                state.recovering_since = None;
+            }
+
+            // Failed checks never feed latency statistics, even while the failure is unconfirmed
+            let mut latency_anomaly = None;
+            if let (Some(anomaly), Some(latency), true) = (&self.config.latency_anomaly, result.latency, available) {
+                let latency_ms = latency.as_secs_f64() * 1000.0;
+                let baseline = &mut state.latency_baseline;
+                let anomalous = baseline.samples >= anomaly.warmup
+                    && baseline.deviation(latency_ms) > anomaly.sigma;
+                if anomalous && !state.latency_anomalous {
+                    latency_anomaly = Some(LatencyAnomaly {
+                        latency_ms,
+                        mean_ms: baseline.mean,
+                        deviation: baseline.deviation(latency_ms),
+                    });
+                }
+                state.latency_anomalous = anomalous;
+                baseline.add(latency_ms);
+            }
+
+            let mut failure_changed = None;
+            if result.status == UrlStatus::Down {
+                let category = result.failure_category();
+                if previous == Some(UrlStatus::Down) {
+                    failure_changed = state.failure_category.filter(|last| *last != category);
+                }
+                state.failure_category = Some(category);
+            } else {
+                state.failure_category = None;
+            }
+
+            state.status = result.status.clone();
+            state.reason = result.reason.clone();
+            state.latency = result.latency.filter(|_| available);
+            state.last_checked = now;
+            state.checks += 1;
+            if available {
+                state.up_checks += 1;
+            }
+
+            results.push(UrlCheck {
+                url: spec.url.clone(),
+                name: spec.name.clone(),
+                tags: spec.tags.clone(),
+                result,
+                previous,
+                latency_anomaly,
+                mentions: spec.mentions.clone(),
+                down_since,
+                auto_disabled,
+                content_changed,
+                failure_changed,
+                expect_down: spec.expect_down,
+            });
+        }
+
+        results
+    }
+
+    /// Runs the startup checks, requiring a minimum share of them to succeed before a URL
+    /// is reported as up so a single spurious success right after boot doesn't count.
+    async fn check_startup_status(&mut self) -> Vec<UrlCheck> {
+        let rounds = self.config.startup_checks;
+        if rounds <= 1 {
+            return self.check_all_urls().await;
+        }
+
+        let mut successes: HashMap<String, u32> = HashMap::new();
+        let mut last_success: HashMap<String, CheckResult> = HashMap::new();
+        let mut last_failure: HashMap<String, CheckResult> = HashMap::new();
+
+        for round in 0..rounds {
+            if round > 0 {
+                sleep(self.config.startup_check_spacing).await;
+            }
+
+            for (url, result) in self.check_hosts().await {
+                if result.status.is_available() {
+                    *successes.entry(url.clone()).or_default() += 1;
+                    last_success.insert(url, result);
+                } else {
+                    last_failure.insert(url, result);
+                }
+            }
+        }
+
+        let required = self.config.startup_success_rate;
+        let decided = self
+            .config
+            .urls
+            .iter()
+            .filter_map(|spec| {
+                let url = spec.url.clone();
+                let up = successes.get(&url).copied().unwrap_or_default();
+                let passed = f64::from(up) / f64::from(rounds) >= required;
+
+                let result = match (last_success.remove(&url), last_failure.remove(&url)) {
+                    (Some(success), _) if passed => success,
+                    (_, Some(failure)) if !passed => {
+                        let summary = format!("{}/{} startup checks succeeded", up, rounds);
+                        CheckResult {
+                            reason: Some(match failure.reason {
+                                Some(reason) => format!("{}; {}", summary, reason),
+                                None => summary,
+                            }),
+                            ..failure
+                        }
+                    }
+                    (_, Some(failure)) => CheckResult {
+                        status: UrlStatus::Up,
+                        reason: None,
+                        ..failure
+                    },
+                    (Some(success), None) => success,
+                    // Turned off through its feature flag
+                    (None, None) => return None,
+                };
+                Some((url, result))
+            })
+            .collect();
+
+        self.record_results(decided)
+    }
+
+    async fn report_initial_status(&mut self) {
+        info!("🚀 Starting URL monitoring...");
+
+        let results = self.check_startup_status().await;
+        self.write_snapshot();
+        self.persist_state().await;
+        self.check_dns().await;
+        let mut status_lines = Vec::new();
+        let mut groups: BTreeMap<Option<&str>, StartupGroup> = BTreeMap::new();
+        let mut severity = Severity::Info;
+
+        for check in &results {
+            // After a restart only URLs that are new or changed while we were away are reported
+            if self.restored && check.previous.as_ref() == Some(&check.result.status) {
+                info!(
+                    "{} {} is {}{}",
+                    check.result.status.emoji(),
+                    check.display_name(),
+                    check.result,
+                    self.tag_suffix(check)
+                );
+                continue;
+            }
+
+            let (line, check_severity) = if check.expect_down {
+                self.decommission_line(check)
+            } else {
+                (self.status_line(check, unix_now(), false), check.result.status.severity())
+            };
+            info!("{}", line);
+            severity = severity.max(check_severity);
+            let lines = match &self.config.startup_group_by {
+                Some(key) => {
+                    let group = groups.entry(check.tags.get(key).map(String::as_str)).or_default();
+                    group.statuses.push(check.result.status.clone());
+                    &mut group.lines
+                }
+                None => &mut status_lines,
+            };
+            lines.push(line);
+            if let Some(line) = self.auto_disabled_line(check) {
+                lines.push(line);
+            }
+        }
+        if let Some(key) = &self.config.startup_group_by {
+            // A restart only reports changes, which are worth listing even when they are recoveries
+            status_lines = grouped_status_lines(key, groups, !self.restored);
+        }
+
+        let version = if self.config.show_version {
+            format!(" {}", version_label())
+        } else {
+            String::new()
+        };
+        let text = if !self.restored {
+            format!(
+                "🔍 *URL Monitor{} Started*\nInitial status check:\n{}",
+                version,
+                status_lines.join("\n")
+            )
+        } else if status_lines.is_empty() {
+            info!("No status changes since the last run");
+            return;
+        } else {
+            format!(
+                "🔍 *URL Monitor{} Restarted*\nChanges since the last run:\n{}",
+                version,
+                status_lines.join("\n")
+            )
+        };
+        let notification = Notification {
+            text,
+            severity,
+            mentions: Vec::new(),
+            incident: None,
+        };
+
+        self.deliver(Destination::Default, notification, "initial status").await;
+    }
+
+    async fn collect_changes(&mut self) -> Vec<Change> {
+        // Failures are more likely the monitor's own connectivity while the canary is down, so
+        // URLs keep their last status until it is back
+        let (canary_up, canary_change) = self.check_canary().await;
+        if !canary_up {
+            return canary_change.into_iter().collect();
+        }
+
+        let results = self.check_all_urls().await;
+        self.write_snapshot();
+        self.persist_state().await;
+        self.send_events(&results).await;
+
+        let mut changes: Vec<Change> = canary_change.into_iter().collect();
+        changes.append(&mut self.pause_changes);
+        let mut recoveries = Vec::new();
+        let now = unix_now();
+
+        // DOWN alerts of URLs whose dependency is DOWN, by the URL at the root of the outage
+        let mut grouped: Vec<(String, &str)> = Vec::new();
+        let mut down_alerts: HashMap<&str, usize> = HashMap::new();
+
+        for check in &results {
+            if check.expect_down {
+                changes.extend(self.decommission_change(check, now));
+                continue;
+            }
+            if let Some(root) = self.independent_failure(check) {
+                let line = format!(
+                    "{} {} is still {} after {} recovered{}",
+                    check.result.status.emoji(),
+                    check.display_name(),
+                    check.result,
+                    self.url_display_name(&root),
+                    self.tag_suffix(check)
+                );
+                info!("Independent failure: {}", line);
+                if !self.should_notify(check, Transition::Down, now, &line) {
+                    continue;
+                }
+                down_alerts.insert(&check.url, changes.len());
+                changes.push(Change {
+                    line,
+                    severity: check.result.status.severity(),
+                    mentions: check.mentions.clone(),
+                    tags: check.tags.clone(),
+                    incident: Some(check.incident()),
+                });
+                continue;
+            }
+
+            if check.status_changed() {
+                let change_msg = self.status_line(check, now, true);
+                info!("Status change: {}", change_msg);
+
+                if check.result.status == UrlStatus::Down {
+                    if let Some(root) = self.root_cause(&check.url) {
+                        info!("Grouping the alert for {} under {}, which it depends on", check.url, root);
+                        if let Some(state) = self.states.get_mut(&check.url) {
+                            state.grouped_under = Some(root.clone());
+                        }
+                        grouped.push((root, check.display_name()));
+                        continue;
+                    }
+                }
+                let grouped_under = self.states.get_mut(&check.url).and_then(|state| state.grouped_under.take());
+                if let (Some(root), Some(UrlStatus::Down)) = (&grouped_under, &check.previous) {
+                    info!("Not alerting the recovery of {}, its outage was grouped under {}", check.url, root);
+                    continue;
+                }
+
+                let transition = match check.result.status {
+                    UrlStatus::Up => Transition::Up,
+                    UrlStatus::Degraded => Transition::Degraded,
+                    UrlStatus::Throttled => Transition::Throttled,
+                    UrlStatus::Down => Transition::Down,
+                };
+                if !self.should_notify(check, transition, now, &change_msg) {
+                    continue;
+                }
+
+                // Only outages ping people; recoveries and warnings don't
+                let mentions = if check.result.status == UrlStatus::Down {
+                    check.mentions.clone()
+                } else {
+                    Vec::new()
+                };
+                if check.previous == Some(UrlStatus::Down) {
+                    recoveries.push(changes.len());
+                }
+                if check.result.status == UrlStatus::Down {
+                    down_alerts.insert(&check.url, changes.len());
+                }
+                changes.push(Change {
+                    line: change_msg,
+                    severity: check.result.status.severity(),
+                    mentions,
+                    tags: check.tags.clone(),
+                    incident: Some(check.incident()),
+                });
+            }
+        }
+
+        let mut dependents: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for (root, name) in grouped {
+            dependents.entry(root).or_default().push(name);
+        }
+        for (root, names) in dependents {
+            match down_alerts.get(root.as_str()) {
+                Some(&index) => {
+                    let line = &mut changes[index].line;
+                    line.push_str(&format!("; dependents also DOWN: {}", names.join(", ")));
+                }
+                None => info!("Not alerting {}, which depend on {}, already DOWN", names.join(", "), root),
+            }
+        }
+
+        for check in results.iter().filter(|check| check.content_changed) {
+            let spec_alerts = self
+                .config
+                .urls
+                .iter()
+                .any(|spec| spec.url == check.url && spec.alert_on_change);
+            let line = format!("📝 {} content changed{}", check.display_name(), self.tag_suffix(check));
+            info!("Content change: {}", line);
+            if !spec_alerts {
+                continue;
+            }
+            if !self.should_notify(check, Transition::Changed, now, &line) {
+                continue;
+            }
+            changes.push(Change {
+                line,
+                severity: Severity::Info,
+                mentions: Vec::new(),
+                tags: check.tags.clone(),
+                incident: None,
+            });
+        }
+
+        if self.config.alert_on_failure_change {
+            for check in results.iter().filter(|check| !check.expect_down) {
+                let Some(previous_failure) = check.failure_changed else {
+                    continue;
+                };
+                if self.states.get(&check.url).is_some_and(|state| state.grouped_under.is_some()) {
+                    continue;
+                }
+                let line = format!(
+                    "{} {} is still {}, failure changed from {} to {}{}",
+                    check.result.status.emoji(),
+                    check.display_name(),
+                    check.result,
+                    previous_failure,
+                    check.result.failure_category(),
+                    self.tag_suffix(check)
+                );
+                info!("Failure change: {}", line);
+                if !self.should_notify(check, Transition::Down, now, &line) {
+                    continue;
+                }
+                changes.push(Change {
+                    line,
+                    severity: check.result.status.severity(),
+                    mentions: check.mentions.clone(),
+                    tags: check.tags.clone(),
+                    incident: Some(check.incident()),
+                });
+            }
+        }
+
+        for check in &results {
+            if let Some(line) = self.auto_disabled_line(check) {
+                changes.push(Change {
+                    line,
+                    severity: Severity::Warning,
+                    mentions: Vec::new(),
+                    tags: check.tags.clone(),
+                    incident: None,
+                });
+            }
+        }
+
+        if self.is_broad_recovery(&results) && !recoveries.is_empty() {
+            let recovered = results
+                .iter()
+                .filter(|check| check.previous == Some(UrlStatus::Down))
+                .count();
+            let line = format!("🎉 All monitored URLs have recovered ({} were down)", recovered);
+            info!("Broad outage over: {}", line);
+            let first = recoveries[0];
+            for index in recoveries.into_iter().rev() {
+                changes.remove(index);
+            }
+            changes.insert(
+                first,
+                Change {
+                    line,
+                    severity: Severity::Info,
+                    mentions: Vec::new(),
+                    tags: BTreeMap::new(),
+                    incident: None,
+                },
+            );
+        }
+
+        changes.extend(self.latency_anomaly_changes(&results, now));
+        self.record_latency_windows(&results);
+        changes.extend(self.latency_comparison_changes(&results, now));
+        changes.extend(self.latency_consistency_changes(&results, now));
+        changes.extend(self.deploy_window_changes(&results, now));
+        changes.extend(self.check_dns().await);
+        changes
+    }
+
+    /// Checks `UPNOTIF_CANARY_URL`, returning whether it is available and a change when it went
+    /// DOWN or recovered. Without a canary every cycle goes ahead.
+    async fn check_canary(&mut self) -> (bool, Option<Change>) {
+        let Some(url) = &self.config.canary_url else {
+            return (true, None);
+        };
+        let result = self.check_url_status(&UrlSpec::plain(url.clone())).await;
+        let up = result.status.is_available();
+
+        let (line, severity) = match (up, self.canary_down) {
+            (false, false) => {
+                let line = format!("⚠️ Canary {} is {}, pausing checks and alerts until it recovers", url, result);
+                warn!("{}", line);
+                (line, Severity::Warning)
+            }
+            (true, true) => {
+                let line = format!("✅ Canary {} recovered, resuming checks and alerts", url);
+                info!("{}", line);
+                (line, Severity::Info)
+            }
+            (false, true) => {
+                info!("Canary {} is still {}, skipping this cycle", url, result);
+                return (false, None);
+            }
+            (true, false) => return (true, None),
+        };
+        self.canary_down = !up;
+
+        let change = Change {
+            line,
+            severity,
+            mentions: Vec::new(),
+            tags: BTreeMap::new(),
+            incident: None,
+        };
+        (up, Some(change))
+    }
+
+    /// Holds the startup report until the canary is available, so starting without connectivity
+    /// doesn't report every URL DOWN.
+    async fn wait_for_canary(&mut self) {
+        while !self.check_canary().await.0 {
+            sleep(Duration::from_secs(self.config.interval_seconds)).await;
+        }
+    }
+
+    /// The DOWN URL where the outage of `url`'s dependencies starts, if any dependency is DOWN.
+    fn root_cause(&self, url: &str) -> Option<String> {
+        let spec = self.config.urls.iter().find(|spec| spec.url == url)?;
+        spec.depends_on.iter().find_map(|dependency| {
+            let down = self.states.get(dependency).is_some_and(|state| state.status == UrlStatus::Down);
+            down.then(|| self.root_cause(dependency).unwrap_or_else(|| dependency.clone()))
+        })
+    }
+
+    /// For a URL still DOWN whose alert was grouped under a dependency that has since recovered,
+    /// returns that dependency: the failure turned out to be the URL's own.
+    fn independent_failure(&mut self, check: &UrlCheck) -> Option<String> {
+        if check.result.status != UrlStatus::Down || check.status_changed() {
+            return None;
+        }
+        let root = self.root_cause(&check.url);
+        let state = self.states.get_mut(&check.url)?;
+        state.grouped_under.as_ref()?;
+        match root {
+            // The outage may now trace back to a different dependency
+            Some(root) => {
+                state.grouped_under = Some(root);
+                None
+            }
+            None => state.grouped_under.take(),
+        }
+    }
+
+    fn url_display_name<'a>(&'a self, url: &'a str) -> &'a str {
+        self.config
+            .urls
+            .iter()
+            .find(|spec| spec.url == url)
+            .and_then(|spec| spec.name.as_deref())
+            .unwrap_or(url)
+    }
+
+    /// Whether this cycle ended an outage of at least `UPNOTIF_BROAD_OUTAGE_MIN_DOWN` URLs with
+    /// every URL back up, in which case the individual recoveries are replaced by one line.
+    fn is_broad_recovery(&self, results: &[UrlCheck]) -> bool {
+        let Some(min_down) = self.config.broad_outage_min_down else {
+            return false;
+        };
+        let previously_down = results
+            .iter()
+            .filter(|check| check.previous == Some(UrlStatus::Down) && !check.expect_down)
+            .count();
+        // URLs skipped this cycle (e.g. in Retry-After backoff) keep their recorded status
+        let still_down = self
+            .config
+            .urls
+            .iter()
+            .filter(|spec| !spec.expect_down)
+            .filter_map(|spec| self.states.get(&spec.url))
+            .any(|state| state.status == UrlStatus::Down && !state.auto_disabled);
+        previously_down >= min_down && !still_down
+    }
+
+    /// Reports a decommissioned URL as unexpectedly UP, or as DOWN as it should be.
+    fn decommission_line(&self, check: &UrlCheck) -> (String, Severity) {
+        if check.result.status.is_available() {
+            let line = format!(
+                "⚠️ Decommissioned service {} is unexpectedly {}{}",
+                check.display_name(),
+                check.result,
+                self.tag_suffix(check)
+            );
+            (line, Severity::Warning)
+        } else {
+            let line = format!(
+                "💤 Decommissioned service {} is DOWN, as expected{}",
+                check.display_name(),
+                self.tag_suffix(check)
+            );
+            (line, Severity::Info)
+        }
+    }
+
+    /// The alert of a decommissioned URL that came back UP, or went DOWN again.
+    fn decommission_change(&self, check: &UrlCheck, now: u64) -> Option<Change> {
+        let up = check.result.status.is_available();
+        if check.previous.as_ref()?.is_available() == up {
+            return None;
+        }
+        let (line, severity) = self.decommission_line(check);
+        info!("Decommissioned service change: {}", line);
+        let transition = if up { Transition::Up } else { Transition::Down };
+        if !self.should_notify(check, transition, now, &line) {
+            return None;
+        }
+        // Someone redeployed what should be gone, which is worth a ping
+        let mentions = if up { check.mentions.clone() } else { Vec::new() };
+        Some(Change {
+            line,
+            severity,
+            mentions,
+            tags: check.tags.clone(),
+            incident: None,
+        })
+    }
+
+    /// Announces a URL that just stopped being checked after persistently returning a "gone" status.
+    fn auto_disabled_line(&self, check: &UrlCheck) -> Option<String> {
+        let code = check.auto_disabled?;
+        let line = format!(
+            "🗑️ Auto-disabled {} after persistent {} ({} checks in a row){}",
+            check.display_name(),
+            code,
+            self.config.auto_disable_after.unwrap_or_default(),
+            self.tag_suffix(check)
+        );
+        info!("{}", line);
+        Some(line)
+    }
+
+    fn latency_anomaly_changes(&self, results: &[UrlCheck], now: u64) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for check in results {
+            let Some(anomaly) = &check.latency_anomaly else {
+                continue;
+            };
+            let line = format!(
+                "📈 {} responded in {:.0}ms, {:.1}σ above its usual {:.0}ms{}",
+                check.display_name(),
+                anomaly.latency_ms,
+                anomaly.deviation,
+                anomaly.mean_ms,
+                self.tag_suffix(check)
+            );
+            info!("Latency anomaly: {}", line);
+
+            if !self.should_notify(check, Transition::Anomaly, now, &line) {
+                continue;
+            }
+            changes.push(Change {
+                line,
+                severity: Severity::Warning,
+                mentions: Vec::new(),
+                tags: check.tags.clone(),
+                incident: None,
+            });
+        }
+        changes
+    }
+
+    /// Reports URLs whose deploy window ended while they are still failing, since the change that
+    /// made them fail was suppressed.
+    fn deploy_window_changes(&mut self, results: &[UrlCheck], now: u64) -> Vec<Change> {
+        let active: HashSet<String> = self.deploy_windows.all(now).into_keys().collect();
+        let ended: Vec<String> = self.in_deploy_window.difference(&active).cloned().collect();
+        self.in_deploy_window = active;
+
+        let mut changes = Vec::new();
+        for check in results.iter().filter(|check| ended.contains(&check.url)) {
+            info!("Deploy window of {} ended", check.url);
+            let failing = self.states.get(&check.url).is_some_and(|state| state.status != UrlStatus::Up);
+            if !failing || check.result.status == UrlStatus::Up {
+                continue;
+            }
+            let line = format!(
+                "{} {} is still {} after its deploy window ended{}",
+                check.result.status.emoji(),
+                check.display_name(),
+                check.result,
+                self.tag_suffix(check)
+            );
+            info!("Status after deploy window: {}", line);
+            let transition = match check.result.status {
+                UrlStatus::Degraded => Transition::Degraded,
+                UrlStatus::Throttled => Transition::Throttled,
+                _ => Transition::Down,
+            };
+            if !self.should_notify(check, transition, now, &line) {
+                continue;
+            }
+            changes.push(Change {
+                line,
+                severity: check.result.status.severity(),
+                mentions: if check.result.status == UrlStatus::Down {
+                    check.mentions.clone()
+                } else {
+                    Vec::new()
+                },
+                tags: check.tags.clone(),
+                incident: Some(check.incident()),
+            });
+        }
+        changes
+    }
+
+    /// Adds this cycle's successful response times to the windows of URLs that compare latency or
+    /// check its consistency.
+    fn record_latency_windows(&mut self, results: &[UrlCheck]) {
+        for check in results {
+            let (true, Some(latency)) = (check.result.status.is_available(), check.result.latency) else {
+                continue;
+            };
+            // A URL may be the reference of several others, so keep enough for the largest window
+            let compared = self
+                .config
+                .urls
+                .iter()
+                .filter_map(|spec| spec.compare_latency.as_ref().map(|comparison| (spec, comparison)))
+                .filter(|(spec, comparison)| spec.url == check.url || comparison.with == check.url)
+                .map(|(_, comparison)| comparison.window);
+            let consistency = self
+                .config
+                .urls
+                .iter()
+                .filter(|spec| spec.url == check.url)
+                .filter_map(|spec| spec.latency_consistency.as_ref())
+                .map(|consistency| consistency.window);
+            let Some(window) = compared.chain(consistency).max() else {
+                continue;
+            };
+            let samples = self.latency_windows.entry(check.url.clone()).or_default();
+            samples.push_back(latency);
+            while samples.len() > window {
+                samples.pop_front();
+            }
+        }
+    }
+
+    /// Reports URLs that became, or stopped being, too slow compared with their reference.
+    fn latency_comparison_changes(&mut self, results: &[UrlCheck], now: u64) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for check in results {
+            let Some(comparison) = self
+                .config
+                .urls
+                .iter()
+                .find(|spec| spec.url == check.url)
+                .and_then(|spec| spec.compare_latency.as_ref())
+            else {
+                continue;
+            };
+            let average = |url: &str| average_ms(self.latency_windows.get(url)?, comparison.window);
+            let (Some(own_ms), Some(reference_ms)) = (average(&check.url), average(&comparison.with)) else {
+                continue;
+            };
+
+            let slower_percent = (own_ms / reference_ms - 1.0) * 100.0;
+            let slower = slower_percent > comparison.max_slower_percent;
+            if slower == self.slower_than_reference.contains(&check.url) {
+                continue;
+            }
+            let reference = self.url_display_name(&comparison.with);
+            let (line, severity) = if slower {
+                let line = format!(
+                    "🐢 {} is {:.0}% slower than {}: {:.0}ms vs {:.0}ms on average over the last {} checks{}",
+                    check.display_name(),
+                    slower_percent,
+                    reference,
+                    own_ms,
+                    reference_ms,
+                    comparison.window,
+                    self.tag_suffix(check)
+                );
+                (line, Severity::Warning)
+            } else {
+                let line = format!(
+                    "✅ {} is back within {}% of {}: {:.0}ms vs {:.0}ms on average{}",
+                    check.display_name(),
+                    comparison.max_slower_percent,
+                    reference,
+                    own_ms,
+                    reference_ms,
+                    self.tag_suffix(check)
+                );
+                (line, Severity::Info)
+            };
+            info!("Latency comparison: {}", line);
+            if slower {
+                self.slower_than_reference.insert(check.url.clone());
+            } else {
+                self.slower_than_reference.remove(&check.url);
+            }
+
+            if !self.should_notify(check, Transition::Anomaly, now, &line) {
+                continue;
+            }
+            changes.push(Change {
+                line,
+                severity,
+                mentions: Vec::new(),
+                tags: check.tags.clone(),
+                incident: None,
+            });
+        }
+        changes
+    }
+
+    /// Reports URLs whose recent response times started, or stopped, varying more than their
+    /// `latency_consistency` allows.
+    fn latency_consistency_changes(&mut self, results: &[UrlCheck], now: u64) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for check in results {
+            let Some(consistency) = self
+                .config
+                .urls
+                .iter()
+                .find(|spec| spec.url == check.url)
+                .and_then(|spec| spec.latency_consistency.as_ref())
+            else {
+                continue;
+            };
+            let Some(samples) = self.latency_windows.get(&check.url).filter(|s| s.len() >= consistency.window) else {
+                continue;
+            };
+            let mut stats = LatencyBaseline::default();
+            for latency in samples.iter().rev().take(consistency.window) {
+                stats.add(latency.as_secs_f64() * 1000.0);
+            }
+
+            let cv = stats.coefficient_of_variation();
+            let erratic = cv > consistency.max_cv;
+            if erratic == self.erratic_latency.contains(&check.url) {
+                continue;
+            }
+            let (line, severity) = if erratic {
+                let line = format!(
+                    "〰️ {} has erratic response times: {:.0}ms ± {:.0}ms over the last {} checks, variation {:.2}, limit {}{}",
+                    check.display_name(),
+                    stats.mean,
+                    stats.std_dev(),
+                    consistency.window,
+                    cv,
+                    consistency.max_cv,
+                    self.tag_suffix(check)
+                );
+                (line, Severity::Warning)
+            } else {
+                let line = format!(
+                    "✅ {} has consistent response times again: {:.0}ms ± {:.0}ms, variation {:.2}{}",
+                    check.display_name(),
+                    stats.mean,
+                    stats.std_dev(),
+                    cv,
+                    self.tag_suffix(check)
+                );
+                (line, Severity::Info)
+            };
+            info!("Latency consistency: {}", line);
+            if erratic {
+                self.erratic_latency.insert(check.url.clone());
+            } else {
+                self.erratic_latency.remove(&check.url);
+            }
+
+            if !self.should_notify(check, Transition::Anomaly, now, &line) {
+                continue;
+            }
+            changes.push(Change {
+                line,
+                severity,
+                mentions: Vec::new(),
+                tags: check.tags.clone(),
+                incident: None,
+            });
+        }
+        changes
+    }
+
+    /// Resolves every host with DNS tracking enabled and reports hosts whose addresses changed.
+    async fn check_dns(&mut self) -> Vec<Change> {
+        let mut hosts: Vec<String> = Vec::new();
+        // Route DNS changes by the tags of the first URL on each host
+        let mut host_tags: HashMap<String, BTreeMap<String, String>> = HashMap::new();
+        for spec in self.config.urls.iter().filter(|spec| spec.track_dns) {
+            let Ok(url) = Url::parse(&spec.url) else {
+                continue;
+            };
+            // IP literals have nothing to resolve
+            if let Some(url::Host::Domain(host)) = url.host() {
+                let host = host.to_lowercase();
+                if !hosts.contains(&host) {
+                    host_tags.insert(host.clone(), spec.tags.clone());
+                    hosts.push(host);
+                }
+            }
+        }
+
+        let lookup_timeout = self.config.connect_timeout;
+        let resolver = &self.dialer.resolver;
+        let lookups = hosts.into_iter().map(|host| async move {
+            let addresses = match timeout(lookup_timeout, resolver.lookup(&host)).await {
+                Ok(Ok(addresses)) => Some(addresses.into_iter().collect::<BTreeSet<_>>()),
+                Ok(Err(e)) => {
+                    warn!("DNS lookup of {} failed: {}", host, e);
+                    None
+                }
+                Err(_) => {
+                    warn!("DNS lookup of {} timed out", host);
+                    None
+                }
+            };
+            (host, addresses)
+        });
+
+        let mut changes = Vec::new();
+        for (host, addresses) in join_all(lookups).await {
+            // A failed lookup keeps the last known addresses; the HTTP check reports the outage
+            let Some(addresses) = addresses.filter(|addresses| !addresses.is_empty()) else {
+                continue;
+            };
+
+            match self.dns_records.insert(host.clone(), addresses.clone()) {
+                None => info!("{} resolves to {}", host, format_addresses(&addresses)),
+                Some(previous) if previous != addresses => {
+                    let line = format!(
+                        "🔀 {} now resolves to {} (was {})",
+                        host,
+                        format_addresses(&addresses),
+                        format_addresses(&previous)
+                    );
+                    info!("DNS change: {}", line);
+                    changes.push(Change {
+                        line,
+                        severity: Severity::Warning,
+                        mentions: Vec::new(),
+                        tags: host_tags.remove(&host).unwrap_or_default(),
+                        incident: None,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        changes
+    }
+
+    /// Sends each change to every route matching its tags and active now, or to the default webhook if none match.
+    async fn send_changes(&mut self, changes: &[Change]) {
+        // One time for all changes, so a batch sent right at a handoff isn't split between rotations
+        let now = unix_now();
+        let mut routed: Vec<(Destination, Vec<&Change>)> = Vec::new();
+        for change in changes {
+            let mut destinations: Vec<Destination> = routing::matching_routes(&self.config.routes, &change.tags, now, self.config.display_timezone)
+                .into_iter()
+                .map(Destination::Route)
+                .collect();
+            if destinations.is_empty() {
+                destinations.push(Destination::Default);
+            }
+            for destination in destinations {
+                match routed.iter_mut().find(|(known, _)| *known == destination) {
+                    Some((_, routed_changes)) => routed_changes.push(change),
+                    None => routed.push((destination, vec![change])),
+                }
+            }
+        }
+
+        for (destination, changes) in routed {
+            if let Destination::Route(index) = destination {
+                info!("Routing {} change(s) to {}", changes.len(), self.config.routes[index].name);
+            }
+            self.send_routed_changes(destination, &changes).await;
+        }
+    }
+
+    async fn send_routed_changes(&mut self, destination: Destination, changes: &[&Change]) {
+        // PagerDuty keeps an incident per URL, so each change is its own event there
+        if let Destination::Route(index) = destination {
+            if self.config.routes[index].routing_key.is_some() {
+                for change in changes {
+                    let notification = Notification {
+                        text: change.line.clone(),
+                        severity: change.severity,
+                        mentions: change.mentions.clone(),
+                        incident: change.incident.clone(),
+                    };
+                    self.deliver(destination, notification, "status change").await;
+                }
+                return;
+            }
+        }
+
+        let lines: Vec<&str> = changes.iter().map(|change| change.line.as_str()).collect();
+        let notification = Notification {
+            text: format!("🔔 *URL Status Changes*\n{}", lines.join("\n")),
+            severity: changes
+                .iter()
+                .map(|change| change.severity)
+                .max()
+                .unwrap_or(Severity::Info),
+            mentions: changes.iter().fold(Vec::new(), |mut mentions, change| {
+                for mention in &change.mentions {
+                    if !mentions.contains(mention) {
+                        mentions.push(mention.clone());
+                    }
+                }
+                mentions
+            }),
+            incident: None,
+        };
+
+        self.deliver(destination, notification, "status change").await;
+    }
+
+    /// Tells each route whose schedule just started which of its URLs are still failing, since their
+    /// alerts went to whoever was on call before. Their recoveries will go to this route.
+    async fn announce_handoffs(&mut self) {
+        let now = unix_now();
+        for index in 0..self.config.routes.len() {
+            let route = &self.config.routes[index];
+            let active = route.is_active(now, self.config.display_timezone);
+            let started = active && !self.active_routes[index];
+            self.active_routes[index] = active;
+            // PagerDuty's own schedules hand open incidents over to the next on-call engineer
+            if !started || !route.is_scheduled() || route.routing_key.is_some() {
+                continue;
+            }
+
+            let lines: Vec<String> = self
+                .config
+                .urls
+                .iter()
+                .filter(|spec| route.matches(&spec.tags) && !spec.expect_down)
+                .filter(|spec| self.deploy_windows.active(&spec.url, now).is_none())
+                .filter_map(|spec| {
+                    let state = self.states.get(&spec.url).filter(|s| s.status != UrlStatus::Up && !s.auto_disabled)?;
+                    let status = match &state.reason {
+                        Some(reason) => format!("{} ({})", state.status, reason),
+                        None => state.status.to_string(),
+                    };
+                    let since = state
+                        .failing_since
+                        .map(|since| format!(" since {}", format_local_time(since, now, self.config.display_timezone)))
+                        .unwrap_or_default();
+                    let name = spec.name.as_deref().unwrap_or(&spec.url);
+                    Some(format!("{} {} is {}{}", state.status.emoji(), name, status, since))
+                })
+                .collect();
+            info!("Route {} is now active, {} of its URLs are failing", route.name, lines.len());
+            if lines.is_empty() {
+                continue;
+            }
+
+            let notification = Notification {
+                text: format!("🔁 *On-call handoff to {}*, still failing:\n{}", route.name, lines.join("\n")),
+                severity: Severity::Warning,
+                mentions: Vec::new(),
+                incident: None,
+            };
+            self.deliver(Destination::Route(index), notification, "on-call handoff").await;
+        }
+    }
+
+    async fn flush_pending_changes(&mut self) {
+        let changes = std::mem::take(&mut self.pending_changes);
+        self.send_changes(&changes).await;
+    }
+
+    fn write_snapshot(&self) {
+        let Some(target) = &self.config.snapshot else {
+            return;
+        };
+
+        let now = unix_now();
+        let urls = self
+            .config
+            .urls
+            .iter()
+            .filter_map(|spec| {
+                let state = self.states.get(&spec.url)?;
+                Some(UrlSnapshot {
+                    url: &spec.url,
+                    name: spec.name.as_deref(),
+                    tags: &spec.tags,
+                    status: state.status.to_string(),
+                    reason: state.reason.as_deref(),
+                    last_checked: state.last_checked,
+                    latency_ms: state.latency.map(|latency| latency.as_millis() as u64),
+                    checks: state.checks,
+                    failed_checks: state.checks - state.up_checks,
+                    uptime_percent: state.uptime_percent(),
+                    deploy_window_until: self.deploy_windows.active(&spec.url, now),
+                })
+            })
+            .collect();
+
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            generated_at: now,
+            urls,
+        };
+
+        if let Err(e) = target.write(&snapshot) {
+            error!("Failed to write status snapshot: {}", e);
+        }
+    }
+
+    /// Saves every URL's status to the state file, notifying once if writing starts failing.
+    async fn persist_state(&mut self) {
+        let Some(path) = &self.config.state_file else {
+            return;
+        };
+
+        let state = PersistedState {
+            version: STATE_VERSION,
+            saved_at: unix_now(),
+            urls: self
+                .states
+                .iter()
+                .map(|(url, state)| {
+                    let persisted = PersistedUrl {
+                        status: state.status.to_string(),
+                        reason: state.reason.clone(),
+                        last_checked: state.last_checked,
+                        checks: state.checks,
+                        up_checks: state.up_checks,
+                        down_since: state.failing_since,
+                    };
+                    (url.clone(), persisted)
+                })
+                .collect(),
+            digest: self.config.resume_digest.then(|| PersistedDigest {
+                started_at: self.digest_started,
+                urls: self
+                    .digest_windows
+                    .iter()
+                    .map(|(url, window)| (url.clone(), window.clone()))
+                    .collect(),
+            }),
+        };
+
+        match state::save(path, &state) {
+            Ok(()) => {
+                if self.state_write_failed {
+                    info!("State file {} written successfully again", path.display());
+                    self.state_write_failed = false;
+                }
+            }
+            Err(e) => {
+                // Keep running from memory; a restart would only re-announce current statuses
+                warn!("Failed to persist state to {}: {}", path.display(), e);
+                if self.state_write_failed {
+                    return;
+                }
+                self.state_write_failed = true;
+                if self.config.notify_state_failures {
+                    let notification = Notification {
+                        text: format!("⚠️ Failed to persist state to {}: {}", path.display(), e),
+                        severity: Severity::Warning,
+                        mentions: Vec::new(),
+                        incident: None,
+                    };
+                    self.deliver(Destination::Default, notification, "state file warning").await;
+                }
+            }
+        }
+    }
+
+    async fn monitor_urls(&mut self, first_check_delay: Duration) {
+        let period = Duration::from_secs(self.config.interval_seconds);
+        let mut schedule = if self.config.align_checks {
+            CheckSchedule::Aligned {
+                period,
+                not_before: SystemTime::now() + first_check_delay,
+            }
+        } else {
+            let mut interval_timer = interval_at(Instant::now() + first_check_delay, period);
+            interval_timer.set_missed_tick_behavior(match self.config.overrun_policy {
+                OverrunPolicy::Skip => MissedTickBehavior::Skip,
+                OverrunPolicy::Extend => MissedTickBehavior::Delay,
+            });
+            CheckSchedule::Fixed(interval_timer)
+        };
+
+        // When batching, changes accumulate across cycles and are sent once per window
+        let mut batch_timer = self.config.batch_window_seconds.map(|secs| {
+            interval_at(Instant::now() + Duration::from_secs(secs), Duration::from_secs(secs))
+        });
+        let mut warmup_timer = self.config.warmup_interval.map(|every| interval_at(Instant::now() + every, every));
+        // A resumed window keeps its schedule, and one that ended while the monitor was down is sent right away
+        let digest_remaining = |every: Duration| {
+            let ends = self.digest_started + every.as_secs();
+            Duration::from_secs(ends.saturating_sub(unix_now()))
+        };
+        let mut digest_timer = self
+            .config
+            .digest_interval
+            .map(|every| interval_at(Instant::now() + digest_remaining(every), every));
+        let mut activity_timer = self.config.activity_interval.map(|every| interval_at(Instant::now() + every, every));
+
+        let drain_on_shutdown = self.config.drain_on_shutdown;
+        let drain_timeout = self.config.drain_timeout;
+        let mut signals = match ShutdownSignals::new() {
+            Ok(signals) => Some(signals),
+            Err(e) => {
+                warn!("Cannot listen for shutdown signals, stopping will abandon checks: {}", e);
+                None
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = schedule.tick() => {
+                    let batching = batch_timer.is_some();
+                    let started = Instant::now();
+                    let stopping = {
+                        let cycle = self.run_cycle(batching);
+                        tokio::pin!(cycle);
+
+                        let stopping = tokio::select! {
+                            _ = &mut cycle => false,
+                            _ = next_signal(&mut signals) => true,
+                        };
+                        if stopping {
+                            if drain_on_shutdown {
+                                info!("Shutdown requested, letting the current check cycle finish (up to {}s)", drain_timeout.as_secs());
+                                tokio::select! {
+                                    finished = timeout(drain_timeout, &mut cycle) => {
+                                        if finished.is_err() {
+                                            warn!("Check cycle did not finish within {}s, abandoning it", drain_timeout.as_secs());
+                                        }
+                                    }
+                                    _ = next_signal(&mut signals) => {
+                                        warn!("Second shutdown signal, abandoning the current check cycle");
+                                    }
+                                }
+                            } else {
+                                info!("Shutdown requested, abandoning the current check cycle");
+                            }
+                        }
+                        stopping
+                    };
+                    if stopping {
+                        break;
+                    }
+                    self.note_cycle_duration(started.elapsed(), period);
+                }
+                _ = next_signal(&mut signals) => {
+                    info!("Shutdown requested");
+                    break;
+                }
+                _ = next_tick(&mut batch_timer) => {
+                    self.flush_pending_changes().await;
+                }
+                _ = next_tick(&mut warmup_timer) => {
+                    self.warm_connections().await;
+                }
+                _ = next_tick(&mut digest_timer) => {
+                    self.send_digest().await;
+                }
+                _ = next_tick(&mut activity_timer) => {
+                    self.send_activity_summary().await;
+                }
+            }
+        }
+
+        self.shut_down().await;
+    }
+
+    /// Warns when a check cycle starts taking longer than the interval, and logs when it fits again.
+    fn note_cycle_duration(&mut self, took: Duration, period: Duration) {
+        let overran = took > period;
+        if overran && !self.overrunning {
+            let next = match self.config.overrun_policy {
+                OverrunPolicy::Skip => "skipping missed cycles until the next scheduled one",
+                OverrunPolicy::Extend => "starting the next cycle right away",
+            };
+            warn!(
+                "Check cycle took {:.1}s, longer than the {}s interval; {}. Raise UPNOTIF_INTERVAL_SECONDS or lower UPNOTIF_REQUEST_TIMEOUT_SECONDS",
+                took.as_secs_f64(),
+                period.as_secs(),
+                next
+            );
+        } else if overran {
+            debug!("Check cycle took {:.1}s, longer than the {}s interval", took.as_secs_f64(), period.as_secs());
+        } else if self.overrunning {
+            info!("Check cycle took {:.1}s and fits in the {}s interval again", took.as_secs_f64(), period.as_secs());
+        }
+        self.overrunning = overran;
+    }
+
+    /// One check of every URL, with the resulting changes sent or held for the batch window.
+    async fn run_cycle(&mut self, batching: bool) {
+        self.flush_undelivered().await;
+        self.announce_handoffs().await;
+        let changes = self.collect_changes().await;
+
+        if batching {
+            self.pending_changes.extend(changes);
+        } else {
+            self.send_changes(&changes).await;
+        }
+    }
+
+    /// Saves state from the last completed cycle and sends changes still waiting for their batch window.
+    async fn shut_down(&mut self) {
+        self.persist_state().await;
+        if !self.pending_changes.is_empty() {
+            self.flush_pending_changes().await;
+        }
+        info!("Stopped monitoring");
+    }
+
+    /// Sends uptime and latency percentiles of every URL since the previous digest, then starts a new window.
+    async fn send_digest(&mut self) {
+        let Some(every) = self.config.digest_interval else {
+            return;
+        };
+        let windows = std::mem::take(&mut self.digest_windows);
+        let now = unix_now();
+        // Longer than the interval when the window was resumed after it had already ended
+        let covered = now.saturating_sub(self.digest_started).max(every.as_secs());
+        self.digest_started = now;
+        let empty = DigestWindow::default();
+        let rows: Vec<DigestRow> = self
+            .config
+            .urls
+            .iter()
+            .map(|spec| DigestRow {
+                name: spec.name.as_deref().unwrap_or(&spec.url),
+                window: windows.get(&spec.url).unwrap_or(&empty),
+            })
+            .collect();
+
+        let title = format!(
+            "📊 *Uptime Digest* (last {}, until {})",
+            format_elapsed(covered),
+            format_local_time(now, now, self.config.display_timezone)
+        );
+        let notification = Notification {
+            text: digest::render(&title, &rows),
+            severity: Severity::Info,
+            mentions: Vec::new(),
+            incident: None,
+        };
+        self.deliver(Destination::Default, notification, "digest").await;
+        // Otherwise a restart before the next save would send this digest again
+        if self.config.resume_digest {
+            self.persist_state().await;
+        }
+    }
+
+    /// Sends how many checks ran since the previous summary and how they failed, then starts a new window.
+    async fn send_activity_summary(&mut self) {
+        let Some(every) = self.config.activity_interval else {
+            return;
+        };
+        let window = std::mem::take(&mut self.activity_window);
+        let now = unix_now();
+        let title = format!(
+            "💓 *Monitor Activity* (last {}, until {})",
+            format_elapsed(every.as_secs()),
+            format_local_time(now, now, self.config.display_timezone)
+        );
+        let notification = Notification {
+            text: activity::render(&title, &window),
+            severity: Severity::Info,
+            mentions: Vec::new(),
+            incident: None,
+        };
+        self.deliver(Destination::Default, notification, "activity summary").await;
+    }
+
+    /// Opens (or reuses) a pooled connection to every monitored origin so that check
+    /// latencies don't include DNS, TCP and TLS setup. Results are ignored.
+    async fn warm_connections(&self) {
+        let mut origins: Vec<String> = Vec::new();
+        for spec_url in self.config.urls.iter().flat_map(UrlSpec::request_urls) {
+            let Some(url) = http_url(spec_url) else {
+                continue;
+            };
+            let origin = url.origin().ascii_serialization();
+            if !origins.contains(&origin) {
+                origins.push(origin);
+            }
+        }
+
+        let warmups = origins.iter().map(|origin| async move {
+            match self.client.head(origin.as_str()).send().await {
+                Ok(response) => debug!("Warmed connection to {} ({})", origin, response.status()),
+                Err(e) => debug!("Warmup request to {} failed: {}", origin, e),
+            }
+        });
+        join_all(warmups).await;
+    }
+
+    /// Sends a test message to every configured channel, returning false if any of them failed.
+    async fn verify_notifiers(&self) -> bool {
+        let notification = Notification {
+            text: "🔌 *URL Monitor* can deliver notifications to this channel".to_string(),
+            severity: Severity::Info,
+            mentions: Vec::new(),
+            incident: None,
+        };
+        let destinations = std::iter::once((Destination::Default, "UPNOTIF_SLACK_WEBHOOK".to_string())).chain(
+            self.config
+                .routes
+                .iter()
+                .enumerate()
+                // PagerDuty cannot take a test event without opening an incident
+                .filter(|(_, route)| route.routing_key.is_none())
+                .map(|(index, route)| (Destination::Route(index), format!("route '{}'", route.name))),
+        );
+
+        let mut delivered = true;
+        for (destination, label) in destinations {
+            if let Err(e) = self.notifier(destination).send(&self.client, &notification).await {
+                error!("❗ Notifications cannot be delivered to {}: {}", label, e);
+                delivered = false;
+            }
+        }
+        delivered
+    }
+
+    async fn run(&mut self) {
+        if self.config.verify_notifier != NotifierCheck::Off && !self.verify_notifiers().await {
+            if self.config.verify_notifier == NotifierCheck::Exit {
+                error!("Exiting because UPNOTIF_VERIFY_NOTIFIER is set to exit");
+                std::process::exit(1);
+            }
+            warn!("Continuing to monitor, but alerts may not arrive until the notifier is fixed");
+        }
+        if self.config.warmup_interval.is_some() {
+            self.warm_connections().await;
+        }
+        // Without saved state there is nothing to take over, so start as usual
+        let handoff = self.config.startup_mode == StartupMode::Handoff && self.restored;
+        if handoff {
+            info!("Taking over the saved state of {} URLs without a startup report", self.states.len());
+        } else {
+            if self.config.startup_mode == StartupMode::Handoff {
+                warn!("No saved state to take over, sending the startup report");
+            }
+            self.wait_for_canary().await;
+            self.report_initial_status().await;
+        }
+
+        info!(
+            "Monitoring {} URLs every {} seconds...",
+            self.config.urls.len(),
+            self.config.interval_seconds
+        );
+
+        // The initial report just checked everything, so by default wait a full interval. A handoff
+        // checks right away instead, as the saved statuses may be an interval old.
+        let first_check_delay = match self.config.first_check_delay {
+            Some(delay) => delay,
+            None if handoff => Duration::ZERO,
+            None => Duration::from_secs(self.config.interval_seconds),
+        };
+        self.monitor_urls(first_check_delay).await;
+    }
+}
+
+/// Loads URL states saved by a previous run, and its digest window with `UPNOTIF_DIGEST_RESUME`, ignoring URLs that
+/// are no longer configured.
+fn restore_states(config: &Config) -> (HashMap<String, UrlState>, Option<PersistedDigest>) {
+    let Some(path) = &config.state_file else {
+        return (HashMap::new(), None);
+    };
+
+    let mut persisted = match state::load(path) {
+        Ok(Some(persisted)) => persisted,
+        Ok(None) => return (HashMap::new(), None),
+        Err(e) => {
+            warn!("Ignoring state file {}: {}", path.display(), e);
+            return (HashMap::new(), None);
+        }
+    };
+
+    let digest = persisted.digest.take().filter(|_| config.resume_digest).map(|mut digest| {
+        digest.urls.retain(|url, _| config.urls.iter().any(|spec| spec.url == *url));
+        info!(
+            "Resuming the digest window started {} ago from {}",
+            format_elapsed(unix_now().saturating_sub(digest.started_at)),
+            path.display()
+        );
+        digest
+    });
+
+    let states: HashMap<String, UrlState> = config
+        .urls
+        .iter()
+        .filter_map(|spec| {
+            let url = persisted.urls.remove(&spec.url)?;
+            let state = UrlState {
+                status: UrlStatus::parse(&url.status)?,
+                reason: url.reason,
+                latency: None,
+                last_checked: url.last_checked,
+                checks: url.checks,
+                up_checks: url.up_checks,
+                latency_baseline: LatencyBaseline::default(),
+                consecutive_failures: 0,
+                failing_since: url.down_since,
+                recovering_since: None,
+                latency_anomalous: false,
+                gone_checks: 0,
+                auto_disabled: false,
+                validators: None,
+                last_body_hash: None,
+                identical_bodies: 0,
+                failure_category: None,
+                grouped_under: None,
+            };
+            Some((spec.url.clone(), state))
+        })
+        .collect();
+
+    info!("Restored the status of {} URLs from {}", states.len(), path.display());
+    (states, digest)
+}
+
+/// Loads the notifications left undelivered by the previous run, and how many it had dropped.
+fn restore_undelivered(config: &Config) -> (VecDeque<(Destination, Notification)>, usize) {
+    let Some(path) = &config.notify_queue_file else {
+        return (VecDeque::new(), 0);
+    };
+
+    let persisted = match outbox::load(path) {
+        Ok(Some(persisted)) => persisted,
+        Ok(None) => return (VecDeque::new(), 0),
+        Err(e) => {
+            match outbox::quarantine(path) {
+                Ok(moved) => warn!(
+                    "Ignoring notification queue file {}: {}, moved it to {}",
+                    path.display(),
+                    e,
+                    moved.display()
+                ),
+                Err(_) => warn!("Ignoring notification queue file {}: {}", path.display(), e),
+            }
+            return (VecDeque::new(), 0);
+        }
+    };
+
+    let mut undelivered: VecDeque<(Destination, Notification)> = persisted
+        .messages
+        .into_iter()
+        .map(|message| {
+            // Routes are matched by name, so messages keep their channel if the route list was reordered
+            let destination = match &message.route {
+                None => Destination::Default,
+                Some(name) => match config.routes.iter().position(|route| route.name == *name) {
+                    Some(index) => Destination::Route(index),
+                    None => {
+                        warn!(
+                            "Route '{}' of a queued notification no longer exists, sending it to the default webhook",
+                            name
+                        );
+                        Destination::Default
+                    }
+                },
+            };
+            let notification = Notification {
+                text: message.text,
+                severity: Severity::parse(&message.severity).unwrap_or(Severity::Warning),
+                mentions: message.mentions.iter().filter_map(|mention| Mention::parse(mention)).collect(),
+                incident: message.incident.map(|key| Incident {
+                    key,
+                    resolved: message.resolved,
+                }),
+            };
+            (destination, notification)
+        })
+        .collect();
+
+    // The queue size may have been lowered since the file was written
+    let mut dropped = persisted.dropped;
+    while undelivered.len() > config.notify_queue_size {
+        undelivered.pop_front();
+        dropped += 1;
+    }
+
+    if !undelivered.is_empty() || dropped > 0 {
+        info!("Loaded {} undelivered notification(s) from {}", undelivered.len(), path.display());
+    }
+    (undelivered, dropped)
+}
+
+/// Formats a Unix timestamp as an RFC 3339 UTC time, e.g. `2024-01-31T14:05:00Z`.
+fn format_utc(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+/// Formats a past timestamp in the display time zone, adding the date unless it is today.
+fn format_local_time(timestamp: u64, now: u64, zone: Tz) -> String {
+    let to_local = |ts: u64| DateTime::from_timestamp(ts as i64, 0).unwrap_or_default().with_timezone(&zone);
+    let time = to_local(timestamp);
+    if time.date_naive() == to_local(now).date_naive() {
+        time.format("%H:%M:%S %Z").to_string()
+    } else {
+        time.format("%Y-%m-%d %H:%M:%S %Z").to_string()
+    }
+}
+
+/// Compact duration using its two largest units, e.g. `45s`, `18m`, `2h 5m` or `3d 4h`.
+fn format_elapsed(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86_400, seconds % 86_400 / 3600, seconds % 3600 / 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, minutes) => format!("{}m", minutes),
+        (0, hours, 0) => format!("{}h", hours),
+        (0, hours, minutes) => format!("{}h {}m", hours, minutes),
+        (days, 0, _) => format!("{}d", days),
+        (days, hours, _) => format!("{}d {}h", days, hours),
+    }
+}
+
+/// URLs of the startup report that share a value of the `UPNOTIF_STARTUP_GROUP_BY` tag.
+#[derive(Default)]
+struct StartupGroup {
+    statuses: Vec<UrlStatus>,
+    lines: Vec<String>,
+}
+
+/// Renders the startup report by group, URLs without the tag last. Groups where every URL is UP are
+/// collapsed to a count when `collapse` is set; the others list their URLs under a summary line.
+fn grouped_status_lines(key: &str, mut groups: BTreeMap<Option<&str>, StartupGroup>, collapse: bool) -> Vec<String> {
+    let untagged = groups.remove(&None);
+    let groups = groups
+        .into_iter()
+        .map(|(value, group)| (format!("{}: {}", key, value.unwrap_or_default()), group))
+        .chain(untagged.map(|group| (format!("no {}", key), group)));
+
+    let mut lines = Vec::new();
+    for (label, group) in groups {
+        let worst = group
+            .statuses
+            .iter()
+            .max_by_key(|status| status.severity())
+            .cloned()
+            .unwrap_or(UrlStatus::Up);
+        if collapse && worst == UrlStatus::Up {
+            let all = if group.statuses.len() > 1 { "all " } else { "" };
+            lines.push(format!("✅ *{}*: {}{} UP", label, all, group.statuses.len()));
+            continue;
+        }
+        let counts: Vec<String> = [UrlStatus::Up, UrlStatus::Degraded, UrlStatus::Throttled, UrlStatus::Down]
+            .into_iter()
+            .filter_map(|status| {
+                let count = group.statuses.iter().filter(|seen| **seen == status).count();
+                (count > 0).then(|| format!("{} {}", count, status))
+            })
+            .collect();
+        lines.push(format!("{} *{}*: {}", worst.emoji(), label, counts.join(", ")));
+        lines.extend(group.lines.into_iter().map(|line| format!("    {}", line)));
+    }
+    lines
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Verifies that the server presents one of the URL's pinned certificates.
+async fn check_pinned_certificate(spec: &UrlSpec, dialer: &tls::Dialer) -> Result<(), String> {
+    let url = Url::parse(&spec.url).map_err(|_| "invalid URL".to_string())?;
+    let host = url.host_str().ok_or("missing host")?;
+    let port = url.port().unwrap_or(443);
+
+    let fingerprint = tls::peer_certificate_sha256(host, port, dialer).await?;
+
+    if spec.cert_fingerprints.contains(&fingerprint) {
+        Ok(())
+    } else {
+        Err(format!(
+            "TLS certificate does not match pinned fingerprint, got {}",
+            tls::format_fingerprint(&fingerprint)
+        ))
+    }
+}
+
+fn format_addresses(addresses: &BTreeSet<IpAddr>) -> String {
+    addresses
+        .iter()
+        .map(IpAddr::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Compresses a request body for `compress_body`.
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn url_host(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .unwrap_or_default()
+}
+
+/// The parsed URL if it is an `http://` or `https://` URL.
+fn http_url(url: &str) -> Option<Url> {
+    Url::parse(url).ok().filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+fn is_http_url(url: &str) -> bool {
+    http_url(url).is_some()
+}
+
+/// Opens and immediately closes a TCP connection to the URL's host and port.
+async fn tcp_probe(url: &str, dialer: &tls::Dialer) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|_| "invalid URL".to_string())?;
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Err("missing host".to_string());
+    };
+    match timeout(dialer.connect_timeout, tls::open_tcp(host, port, dialer)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => Err("connection refused".to_string()),
+        Ok(Err(e)) => Err(format!("connection failed: {}", e)),
+        Err(_) => Err("connection timed out".to_string()),
+    }
+}
+
+/// Combines a TCP probe with the HTTP outcome, e.g. "TCP open, HTTP error 500".
+fn describe_layers(tcp: Result<(), String>, status_code: Option<u16>, reason: Option<&str>) -> String {
+    let http = match (status_code, reason) {
+        (Some(code), Some(reason)) => format!("HTTP {}: {}", code, reason),
+        (Some(code), None) => format!("HTTP error {}", code),
+        (None, Some(reason)) => format!("HTTP {}", reason),
+        (None, None) => "HTTP request failed".to_string(),
+    };
+    match tcp {
+        Ok(()) => format!("TCP open, {}", http),
+        // Without a TCP connection the HTTP error adds nothing
+        Err(e) => format!("TCP {}", e),
+    }
+}
+
+/// Raised by the redirect policy when a redirect chain that went over HTTPS continues over plain HTTP.
+#[derive(Debug)]
+struct HttpsDowngrade(Url);
+
+impl std::fmt::Display for HttpsDowngrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "downgraded to HTTP during redirect to {}", self.0)
+    }
+}
+
+impl std::error::Error for HttpsDowngrade {}
+
+/// Follows redirects like reqwest's default policy, but stops before any hop from HTTPS to HTTP.
+fn downgrade_detecting_policy() -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        let downgraded =
+            attempt.url().scheme() == "http" && attempt.previous().iter().any(|url| url.scheme() == "https");
+        if downgraded {
+            let url = attempt.url().clone();
+            attempt.error(HttpsDowngrade(url))
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Finds an HTTPS downgrade among the causes of a failed request.
+fn https_downgrade(error: &reqwest::Error) -> Option<&HttpsDowngrade> {
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if let Some(downgrade) = cause.downcast_ref::<HttpsDowngrade>() {
+            return Some(downgrade);
+        }
+        source = cause.source();
+    }
+    None
+}
+
+/// Sorts a failed request into a failure category. reqwest only flags timeouts and connect errors,
+/// so TLS and DNS failures are recognized from the error chain.
+fn classify_error(error: &reqwest::Error) -> FailureCategory {
+    if error.is_timeout() {
+        return FailureCategory::Timeout;
+    }
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if cause.downcast_ref::<tokio_rustls::rustls::Error>().is_some() {
+            return FailureCategory::Tls;
+        }
+        // The server dropped an established connection
+        if cause.downcast_ref::<hyper::Error>().is_some_and(hyper::Error::is_incomplete_message)
+            || cause.downcast_ref::<std::io::Error>().is_some_and(|e| {
+                matches!(
+                    e.kind(),
+                    std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::ConnectionAborted | std::io::ErrorKind::BrokenPipe
+                )
+            })
+        {
+            return FailureCategory::Connection;
+        }
+        // hyper's resolver error isn't public, but its message is stable
+        if cause.to_string().starts_with("dns error") {
+            return FailureCategory::Dns;
+        }
+        source = cause.source();
+    }
+    if error.is_connect() {
+        FailureCategory::Connection
+    } else {
+        FailureCategory::Other
+    }
+}
+
+/// Describes a redirect sent without the `Location` header it needs; only 300 and 304 may omit it.
+fn malformed_redirect(response: &reqwest::Response) -> Option<String> {
+    let status = response.status();
+    let needs_location =
+        status.is_redirection() && !matches!(status, StatusCode::MULTIPLE_CHOICES | StatusCode::NOT_MODIFIED);
+    (needs_location && !response.headers().contains_key(reqwest::header::LOCATION))
+        .then(|| format!("malformed redirect: {} without a Location header", status.as_u16()))
+}
+
+/// Parses a `Retry-After` header given either as delay seconds or as an HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .ok(),
+    }
+}
+
+/// Checks the response headers against the URL's `expect_headers` patterns, naming the first failing header.
+fn check_headers(spec: &UrlSpec, response: &reqwest::Response) -> Result<(), String> {
+    for (name, pattern) in &spec.expect_headers {
+        let values: Vec<&str> = response
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| value.to_str().unwrap_or_default())
+            .collect();
+        if values.is_empty() {
+            return Err(format!("missing header {}", name));
+        }
+        // A header sent several times passes if any of its values matches
+        if !values.iter().any(|value| pattern.is_match(value)) {
+            return Err(format!(
+                "header {} is \"{}\", expected to match {}",
+                name,
+                values.join(", "),
+                pattern
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the response's `Set-Cookie` headers against the URL's `expect_cookies`. A missing cookie is an error naming
+/// the cookie, and cookies that lack their expected attributes are returned as a warning.
+fn check_cookies(spec: &UrlSpec, response: &reqwest::Response) -> Result<Option<String>, String> {
+    let mut warnings = Vec::new();
+    for expected in &spec.expect_cookies {
+        // A cookie set more than once ends up with its last value, as in a browser
+        let attributes = response
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .rev()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| {
+                let mut parts = value.split(';');
+                let (name, _) = parts.next()?.split_once('=')?;
+                (name.trim() == expected.name).then(|| parts.map(str::trim).collect::<Vec<&str>>())
+            })
+            .next()
+            .ok_or_else(|| format!("missing cookie {}", expected.name))?;
+
+        let has = |attribute: &str| attributes.iter().any(|a| a.eq_ignore_ascii_case(attribute));
+        let same_site = attributes.iter().find_map(|a| {
+            let (name, value) = a.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("samesite").then(|| value.trim())
+        });
+        let mut missing = Vec::new();
+        if expected.secure && !has("secure") {
+            missing.push("Secure".to_string());
+        }
+        if expected.http_only && !has("httponly") {
+            missing.push("HttpOnly".to_string());
+        }
+        match (&expected.same_site, same_site) {
+            (Some(wanted), Some(actual)) if !wanted.eq_ignore_ascii_case(actual) => {
+                missing.push(format!("SameSite={} (has SameSite={})", wanted, actual));
+            }
+            (Some(wanted), None) => missing.push(format!("SameSite={}", wanted)),
+            _ => {}
+        }
+        if !missing.is_empty() {
+            warnings.push(format!("cookie {} lacks {}", expected.name, missing.join(", ")));
+        }
+    }
+    Ok((!warnings.is_empty()).then(|| warnings.join("; ")))
+}
+
+/// Checks the body against the URL's `must_contain` and `must_not_contain` strings, naming the first failing one.
+fn check_content(spec: &UrlSpec, body: &str) -> Result<(), String> {
+    if let Some(missing) = spec.must_contain.iter().find(|text| !body.contains(text.as_str())) {
+        return Err(format!("body does not contain \"{}\"", missing));
+    }
+    if let Some(forbidden) = spec.must_not_contain.iter().find(|text| body.contains(text.as_str())) {
+        return Err(format!("body contains \"{}\"", forbidden));
+    }
+    Ok(())
+}
+
+/// The body around a JSON parse error as inline code, so an HTML error page or a truncated document
+/// is recognizable in the alert.
+fn json_error_snippet(body: &[u8], error: &serde_json::Error) -> String {
+    const CONTEXT: usize = 20;
+    let text = String::from_utf8_lossy(body);
+    let line = text.lines().nth(error.line().saturating_sub(1)).unwrap_or_default();
+    let chars: Vec<char> = line.chars().collect();
+    // serde_json reports the column of the last byte it read, or 0 for an empty line
+    let at = error.column().saturating_sub(1).min(chars.len());
+    let start = at.saturating_sub(CONTEXT);
+    let end = (at + CONTEXT).min(chars.len());
+    let snippet: String = chars[start..end]
+        .iter()
+        .map(|c| match c {
+            '`' => '\'',
+            c if c.is_control() => ' ',
+            c => *c,
+        })
+        .collect();
+    format!(
+        "`{}{}{}`",
+        if start > 0 { "…" } else { "" },
+        snippet,
+        if end < chars.len() { "…" } else { "" }
+    )
+}
+
+/// Validates a JSON response body against the URL's schema and assertions, reporting the first violation.
+fn check_json_body(spec: &UrlSpec, charset: Option<String>, body: &[u8]) -> CheckResult {
+    // JSON has to be UTF-8 (RFC 8259); report mis-encoded bodies separately from malformed JSON
+    let is_utf8 = |charset: &String| charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("utf8");
+    if let Some(charset) = charset.filter(|charset| !is_utf8(charset)) {
+        return CheckResult::down(Some(format!("invalid response encoding: declared charset {}", charset)));
+    }
+    if let Err(e) = std::str::from_utf8(body) {
+        return CheckResult::down(Some(format!(
+            "invalid response encoding: not valid UTF-8 at byte {}",
+            e.valid_up_to()
+        )));
+    }
+
+    let document: serde_json::Value = match serde_json::from_slice(body) {
+        Ok(document) => document,
+        Err(e) => return CheckResult::down(Some(format!("invalid JSON: {}, near {}", e, json_error_snippet(body, &e)))),
+    };
+
+    if let Some(schema) = &spec.json_schema {
+        match schema.validate(&document) {
+            Ok(()) => {}
+            Err(e) if e.instance_path().as_str().is_empty() => {
+                return CheckResult::down(Some(format!("schema violation: {}", e)))
+            }
+            Err(e) => return CheckResult::down(Some(format!("schema violation at {}: {}", e.instance_path(), e))),
+        }
+    }
+
+    match spec.assertions.iter().find_map(|assertion| assertion.evaluate(&document).err()) {
+        Some(failure) => {
+            let reason = Some(format!("assertion failed: {}", failure));
+            match spec.assertion_status {
+                AssertionStatus::Down => CheckResult::down(reason),
+                AssertionStatus::Degraded => CheckResult {
+                    status: UrlStatus::Degraded,
+                    ..CheckResult::down(reason)
+                },
+            }
+        }
+        None => CheckResult::up(),
+    }
+}
+
+/// The `charset` parameter of the response's `Content-Type`, if any.
+fn response_charset(response: &reqwest::Response) -> Option<String> {
+    let content_type = response.headers().get(reqwest::header::CONTENT_TYPE)?.to_str().ok()?;
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// The signals that stop the monitor: SIGTERM (sent by `docker stop` and Kubernetes) and Ctrl+C.
+struct ShutdownSignals {
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl ShutdownSignals {
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            terminate: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = self.terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+        #[cfg(not(unix))]
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Waits for a shutdown signal; never completes if the signals couldn't be listened for.
+async fn next_signal(signals: &mut Option<ShutdownSignals>) {
+    match signals {
+        Some(signals) => signals.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// When the next check cycle starts.
+enum CheckSchedule {
+    Fixed(Interval),
+    /// Each wait is computed from the wall clock, so cycles stay on the boundaries even if the
+    /// clock is adjusted, and boundaries missed by a long cycle are skipped.
+    Aligned { period: Duration, not_before: SystemTime },
+}
+
+impl CheckSchedule {
+    async fn tick(&mut self) {
+        match self {
+            CheckSchedule::Fixed(interval) => {
+                interval.tick().await;
+            }
+            CheckSchedule::Aligned { period, not_before } => {
+                let now = SystemTime::now();
+                let boundary = next_boundary(now.max(*not_before), *period);
+                sleep(boundary.duration_since(now).unwrap_or_default()).await;
+                // The sleep may end just before the boundary by the wall clock, which must not fire it twice
+                *not_before = boundary + Duration::from_nanos(1);
+            }
+        }
+    }
+}
+
+/// The first multiple of `period` since the Unix epoch at or after `time`.
+fn next_boundary(time: SystemTime, period: Duration) -> SystemTime {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let period = period.as_nanos().max(1);
+    let boundary = since_epoch.div_ceil(period) * period;
+    UNIX_EPOCH + Duration::from_nanos(boundary as u64)
+}
+
+/// Waits for the next tick of an optional timer; never completes while it is disabled.
+async fn next_tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Sets up logging, with timestamps in `UPNOTIF_TIMEZONE` when it names a zone other than UTC.
+fn init_logging() {
+    let mut builder = env_logger::Builder::from_default_env();
+    builder.filter_level(log::LevelFilter::Info);
+
+    // An invalid zone keeps the default UTC format here and is reported when the configuration loads
+    let zone = env::var("UPNOTIF_TIMEZONE")
+        .ok()
+        .and_then(|value| value.trim().parse::<Tz>().ok())
+        .filter(|zone| *zone != Tz::UTC);
+    if let Some(zone) = zone {
+        builder.format(move |buf, record| {
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {}",
+                Utc::now().with_timezone(&zone).format("%Y-%m-%dT%H:%M:%S%:z"),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        });
+    }
+    builder.init();
+}
+
+#[tokio::main]
+async fn main() {
+    init_logging();
+
+    info!("upnotif {}", version_label());
+
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Configuration error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    info!("Configuration loaded successfully");
+    info!(
+        "URLs to monitor: {:?}",
+        config.urls.iter().map(|spec| &spec.url).collect::<Vec<_>>()
+    );
+    info!("Check interval: {} seconds", config.interval_seconds);
+    if config.align_checks {
+        info!("Aligning check cycles to multiples of {} seconds on the clock", config.interval_seconds);
+    }
+    if let Some(window) = config.batch_window_seconds {
+        info!("Batching notifications into {} second windows", window);
+    }
+    if let Some(SnapshotTarget::File(path)) = &config.snapshot {
+        info!("Writing status snapshots to {}", path.display());
+    }
+    if let Some(event_webhook) = &config.event_webhook {
+        info!("Sending structured status events to {}", url_host(event_webhook));
+    }
+    if let Some(event_socket) = &config.event_socket {
+        info!("Publishing status events on Unix socket {}", event_socket.display());
+    }
+    if let Some(otlp) = &config.otlp {
+        info!("Exporting a trace of every check to {}", otlp.endpoint);
+    }
+    if let Some(canary_url) = &config.canary_url {
+        info!("Pausing checks and alerts while canary {} is down", canary_url);
+    }
     if config.test_mode {
         info!("Running in TEST MODE - notifications will be logged to console instead of sent to Slack");
     }
@@ -0,0 +1,226 @@
+//! Prometheus metrics served over plain HTTP at `/metrics`.
+//!
+//! Response times are exported as a histogram with configurable buckets rather than a gauge of
+//! the last value, so percentiles can be computed in Prometheus with `histogram_quantile`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+/// The Prometheus client libraries' default buckets, in seconds.
+pub const DEFAULT_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Largest request head read before giving up on a scrape request.
+const MAX_REQUEST_LEN: usize = 8192;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Parses comma-separated bucket upper bounds in seconds, which must be positive and increasing.
+pub fn parse_buckets(value: &str) -> Option<Vec<f64>> {
+    let buckets = value
+        .split(',')
+        .map(|bound| bound.trim().parse::<f64>().ok().filter(|bound| bound.is_finite() && *bound > 0.0))
+        .collect::<Option<Vec<_>>>()?;
+    let increasing = buckets.windows(2).all(|pair| pair[0] < pair[1]);
+    (!buckets.is_empty() && increasing).then_some(buckets)
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last entry is the `+Inf` bucket.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &[f64]) -> Self {
+        Self {
+            counts: vec![0; buckets.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, buckets: &[f64], value: Duration) {
+        let seconds = value.as_secs_f64();
+        let bucket = buckets.iter().position(|bound| seconds <= *bound).unwrap_or(buckets.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct UrlMetrics {
+    up: bool,
+    checks: u64,
+    /// Checks that failed and so have no response time in the histograms.
+    failures: u64,
+    durations: Histogram,
+    /// Only observed for URLs that measure the time to first byte.
+    ttfb: Histogram,
+}
+
+/// Metrics shared between the monitor, which records every check, and the HTTP server.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    buckets: Arc<[f64]>,
+    urls: Arc<Mutex<BTreeMap<String, UrlMetrics>>>,
+}
+
+impl Metrics {
+    pub fn new(buckets: Vec<f64>) -> Self {
+        Self {
+            buckets: buckets.into(),
+            urls: Arc::default(),
+        }
+    }
+
+    /// Records one check; the response times only count when the check succeeded.
+    pub fn record(&self, url: &str, available: bool, latency: Option<Duration>, ttfb: Option<Duration>) {
+        let mut urls = self.urls.lock().unwrap_or_else(|e| e.into_inner());
+        let metrics = urls.entry(url.to_string()).or_insert_with(|| UrlMetrics {
+            up: available,
+            checks: 0,
+            failures: 0,
+            durations: Histogram::new(&self.buckets),
+            ttfb: Histogram::new(&self.buckets),
+        });
+        metrics.up = available;
+        metrics.checks += 1;
+
+        if !available {
+            metrics.failures += 1;
+            return;
+        }
+        if let Some(latency) = latency {
+            metrics.durations.observe(&self.buckets, latency);
+        }
+        if let Some(ttfb) = ttfb {
+            metrics.ttfb.observe(&self.buckets, ttfb);
+        }
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let urls = self.urls.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        out.push_str("# HELP upnotif_up Whether the last check of the URL succeeded.\n");
+        out.push_str("# TYPE upnotif_up gauge\n");
+        for (url, metrics) in urls.iter() {
+            let _ = writeln!(out, "upnotif_up{{url=\"{}\"}} {}", escape(url), u8::from(metrics.up));
+        }
+
+        out.push_str("# HELP upnotif_checks_total Checks run per URL.\n");
+        out.push_str("# TYPE upnotif_checks_total counter\n");
+        for (url, metrics) in urls.iter() {
+            let _ = writeln!(out, "upnotif_checks_total{{url=\"{}\"}} {}", escape(url), metrics.checks);
+        }
+
+        out.push_str("# HELP upnotif_check_failures_total Failed checks per URL, which are left out of the response times.\n");
+        out.push_str("# TYPE upnotif_check_failures_total counter\n");
+        for (url, metrics) in urls.iter() {
+            let _ = writeln!(out, "upnotif_check_failures_total{{url=\"{}\"}} {}", escape(url), metrics.failures);
+        }
+
+        out.push_str("# HELP upnotif_request_duration_seconds Response time of successful checks.\n");
+        out.push_str("# TYPE upnotif_request_duration_seconds histogram\n");
+        for (url, metrics) in urls.iter() {
+            self.render_histogram(&mut out, "upnotif_request_duration_seconds", url, &metrics.durations);
+        }
+
+        if urls.values().any(|metrics| metrics.ttfb.count > 0) {
+            out.push_str("# HELP upnotif_time_to_first_byte_seconds Time until the response headers arrived, of successful checks.\n");
+            out.push_str("# TYPE upnotif_time_to_first_byte_seconds histogram\n");
+            for (url, metrics) in urls.iter().filter(|(_, metrics)| metrics.ttfb.count > 0) {
+                self.render_histogram(&mut out, "upnotif_time_to_first_byte_seconds", url, &metrics.ttfb);
+            }
+        }
+
+        out
+    }
+
+    fn render_histogram(&self, out: &mut String, name: &str, url: &str, histogram: &Histogram) {
+        let url = escape(url);
+        let mut cumulative = 0;
+        for (bound, count) in self.buckets.iter().zip(&histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{url=\"{}\",le=\"{}\"}} {}", name, url, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{url=\"{}\",le=\"+Inf\"}} {}", name, url, histogram.count);
+        let _ = writeln!(out, "{}_sum{{url=\"{}\"}} {}", name, url, histogram.sum);
+        let _ = writeln!(out, "{}_count{{url=\"{}\"}} {}", name, url, histogram.count);
+    }
+
+    /// Starts serving `/metrics` on `address` in the background.
+    pub fn serve(&self, address: SocketAddr) -> io::Result<()> {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+
+        let metrics = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(respond(stream, metrics.clone()));
+                    }
+                    Err(e) => {
+                        // Usually out of file descriptors, so wait instead of spinning
+                        log::warn!("Failed to accept metrics connection: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Answers one scrape request and closes the connection.
+async fn respond(mut stream: TcpStream, metrics: Metrics) {
+    let Ok(Some(head)) = timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await else {
+        return;
+    };
+
+    let mut parts = head.lines().next().unwrap_or_default().split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        (Some("GET"), _) => ("404 Not Found", "Not found, metrics are at /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", "Only GET is supported\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Reads the request line and headers of a request, without its body.
+pub async fn read_request_head(stream: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let len = stream.read(&mut buf).await.ok()?;
+        if len == 0 || head.len() + len > MAX_REQUEST_LEN {
+            return None;
+        }
+        head.extend_from_slice(&buf[..len]);
+    }
+    Some(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// Escapes a label value as the exposition format requires.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
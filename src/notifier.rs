@@ -0,0 +1,497 @@
+use log::{info, warn};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+/// Number of delivery attempts before a notification is considered failed.
+const DELIVERY_ATTEMPTS: u32 = 3;
+
+/// Slack recommends keeping message text under 4,000 characters and truncates longer ones.
+const SLACK_MAX_MESSAGE_LEN: usize = 4000;
+
+/// PagerDuty Events API v2 endpoint that routes events by their integration key.
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+/// PagerDuty rejects events whose summary is longer than 1,024 characters.
+const PAGERDUTY_MAX_SUMMARY_LEN: usize = 1024;
+
+/// How urgent a notification is; a message covering several changes takes the highest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+impl Severity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "info" => Some(Severity::Info),
+            "warning" => Some(Severity::Warning),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// A human-readable message together with its severity.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub text: String,
+    pub severity: Severity,
+    /// Who to ping; rendered in the notifier's own mention syntax.
+    pub mentions: Vec<Mention>,
+    /// The URL problem this message opens or closes, for notifiers that track incidents.
+    pub incident: Option<Incident>,
+}
+
+/// An ongoing problem with one URL, which PagerDuty keeps as a single incident.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incident {
+    /// Deduplication key, the monitored URL.
+    pub key: String,
+    /// Whether the URL recovered, which resolves the incident.
+    pub resolved: bool,
+}
+
+/// Someone to notify directly when a message is sent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mention {
+    User(String),
+    Group(String),
+    Here,
+    Channel,
+}
+
+impl Mention {
+    /// Parses a Slack user ID (`U...` or `W...`), user group ID (`S...`), `here` or `channel`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().trim_start_matches('@');
+        match value {
+            "here" => return Some(Mention::Here),
+            "channel" => return Some(Mention::Channel),
+            _ => {}
+        }
+        if value.len() < 2 || !value.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+            return None;
+        }
+        match value.as_bytes()[0] {
+            b'U' | b'W' => Some(Mention::User(value.to_string())),
+            b'S' => Some(Mention::Group(value.to_string())),
+            _ => None,
+        }
+    }
+
+    fn slack(&self) -> String {
+        match self {
+            Mention::User(id) => format!("<@{}>", id),
+            Mention::Group(id) => format!("<!subteam^{}>", id),
+            Mention::Here => "<!here>".to_string(),
+            Mention::Channel => "<!channel>".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Mention {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Mention::User(id) | Mention::Group(id) => write!(f, "@{}", id),
+            Mention::Here => write!(f, "@here"),
+            Mention::Channel => write!(f, "@channel"),
+        }
+    }
+}
+
+/// What to do with a message longer than the notifier accepts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Send the message as several consecutive messages.
+    Split,
+    /// Send as many lines as fit, followed by "…and N more".
+    Truncate,
+}
+
+impl Overflow {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "split" => Some(Overflow::Split),
+            "truncate" => Some(Overflow::Truncate),
+            _ => None,
+        }
+    }
+}
+
+impl Notification {
+    /// Breaks the message into pieces of at most `limit` characters, preferring line boundaries.
+    pub fn fit(self, limit: usize, overflow: Overflow) -> Vec<Notification> {
+        if self.text.chars().count() <= limit {
+            return vec![self];
+        }
+
+        // Lines longer than the limit on their own have to be cut
+        let lines: Vec<String> = self
+            .text
+            .lines()
+            .flat_map(|line| {
+                let chars: Vec<char> = line.chars().collect();
+                if chars.is_empty() {
+                    vec![String::new()]
+                } else {
+                    chars.chunks(limit).map(|chunk| chunk.iter().collect()).collect()
+                }
+            })
+            .collect();
+
+        let texts = match overflow {
+            Overflow::Split => {
+                let mut parts = Vec::new();
+                let mut current = String::new();
+                let mut current_len = 0;
+                for line in lines {
+                    let line_len = line.chars().count();
+                    if current_len > 0 && current_len + 1 + line_len > limit {
+                        parts.push(std::mem::take(&mut current));
+                        current_len = 0;
+                    }
+                    if current_len > 0 {
+                        current.push('\n');
+                        current_len += 1;
+                    }
+                    current.push_str(&line);
+                    current_len += line_len;
+                }
+                if !current.is_empty() {
+                    parts.push(current);
+                }
+                parts
+            }
+            Overflow::Truncate => {
+                let more = |count: usize| format!("…and {} more", count);
+                let mut kept = String::new();
+                let mut kept_len = 0;
+                for (index, line) in lines.iter().enumerate() {
+                    let line_len = line.chars().count() + usize::from(kept_len > 0);
+                    let remaining = lines.len() - index - 1;
+                    // Leave room for the summary line whenever more lines would follow
+                    let reserved = if remaining > 0 { more(remaining).chars().count() + 1 } else { 0 };
+                    if kept_len + line_len + reserved > limit {
+                        if kept_len > 0 {
+                            kept.push('\n');
+                        }
+                        kept.push_str(&more(lines.len() - index));
+                        break;
+                    }
+                    if kept_len > 0 {
+                        kept.push('\n');
+                    }
+                    kept.push_str(line);
+                    kept_len += line_len;
+                }
+                vec![kept]
+            }
+        };
+
+        // Only the first part pings, the rest continue the same alert
+        let mut mentions = Some(self.mentions);
+        texts
+            .into_iter()
+            .map(|text| Notification {
+                text,
+                severity: self.severity,
+                mentions: mentions.take().unwrap_or_default(),
+                incident: self.incident.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Slack attachment colors used for each severity.
+#[derive(Debug, Clone)]
+pub struct SeverityColors {
+    info: String,
+    warning: String,
+    critical: String,
+}
+
+impl Default for SeverityColors {
+    fn default() -> Self {
+        Self {
+            info: "#2eb886".to_string(),
+            warning: "#daa038".to_string(),
+            critical: "#a30200".to_string(),
+        }
+    }
+}
+
+impl SeverityColors {
+    /// Parses overrides such as `critical=#ff0000,info=good`; unlisted severities keep their default.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut colors = Self::default();
+        for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (severity, color) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid entry '{}', expected severity=color", entry))?;
+            let color = color.trim();
+            if color.is_empty() {
+                return Err(format!("Missing color for '{}'", severity.trim()));
+            }
+            let slot = match severity.trim().to_lowercase().as_str() {
+                "info" => &mut colors.info,
+                "warning" => &mut colors.warning,
+                "critical" => &mut colors.critical,
+                other => {
+                    return Err(format!(
+                        "Unknown severity '{}', expected info, warning or critical",
+                        other
+                    ))
+                }
+            };
+            *slot = color.to_string();
+        }
+        Ok(colors)
+    }
+
+    fn color(&self, severity: Severity) -> &str {
+        match severity {
+            Severity::Info => &self.info,
+            Severity::Warning => &self.warning,
+            Severity::Critical => &self.critical,
+        }
+    }
+}
+
+/// Most messages a backend accepts per period, e.g. `20/minute`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub count: usize,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    /// Parses `<count>/<second|minute|hour>`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (count, unit) = value.trim().split_once('/')?;
+        let count = count.trim().parse().ok().filter(|count| *count > 0)?;
+        let per = match unit.trim().to_lowercase().as_str() {
+            "second" => Duration::from_secs(1),
+            "minute" => Duration::from_secs(60),
+            "hour" => Duration::from_secs(3600),
+            _ => return None,
+        };
+        Some(Self { count, per })
+    }
+}
+
+impl std::fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.per.as_secs() {
+            1 => "second",
+            60 => "minute",
+            _ => "hour",
+        };
+        write!(f, "{}/{}", self.count, unit)
+    }
+}
+
+/// Sliding-window limiter owned by one backend, so each is throttled independently.
+pub struct Throttle {
+    limit: RateLimit,
+    sent: Mutex<VecDeque<Instant>>,
+}
+
+impl Throttle {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            sent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Takes a slot for one message, or fails with how long until the next slot frees up.
+    fn acquire(&self) -> Result<(), String> {
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while sent.front().is_some_and(|at| now.duration_since(*at) >= self.limit.per) {
+            sent.pop_front();
+        }
+        if sent.len() >= self.limit.count {
+            let wait = self.limit.per.saturating_sub(now.duration_since(sent[0]));
+            return Err(format!(
+                "rate limit of {} reached, next slot in {}s",
+                self.limit,
+                wait.as_secs().max(1)
+            ));
+        }
+        sent.push_back(now);
+        Ok(())
+    }
+}
+
+/// Destination for human-readable notification messages.
+pub enum Notifier {
+    /// Test mode: messages are logged to the console.
+    Console,
+    Slack {
+        webhook: String,
+        colors: SeverityColors,
+        throttle: Option<Throttle>,
+    },
+    /// PagerDuty Events API v2, for routes that page the on-call engineer.
+    PagerDuty {
+        routing_key: String,
+    },
+}
+
+impl Notifier {
+    /// Longest message, in characters, the backend accepts.
+    pub fn max_message_len(&self) -> Option<usize> {
+        match self {
+            Notifier::Console => None,
+            Notifier::Slack { .. } => Some(SLACK_MAX_MESSAGE_LEN),
+            Notifier::PagerDuty { .. } => Some(PAGERDUTY_MAX_SUMMARY_LEN),
+        }
+    }
+
+    pub async fn send(&self, client: &Client, notification: &Notification) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Notifier::Console => {
+                let mentions: Vec<String> = notification.mentions.iter().map(Mention::to_string).collect();
+                if mentions.is_empty() {
+                    info!(
+                        "[TEST MODE] Slack notification ({}): {}",
+                        notification.severity, notification.text
+                    );
+                } else {
+                    info!(
+                        "[TEST MODE] Slack notification ({}) for {}: {}",
+                        notification.severity,
+                        mentions.join(" "),
+                        notification.text
+                    );
+                }
+                Ok(())
+            }
+            Notifier::Slack { webhook, colors, throttle } => {
+                if let Some(throttle) = throttle {
+                    throttle.acquire().map_err(|e| format!("Slack webhook {}", e))?;
+                }
+
+                // The attachment color bar shows the severity at a glance; fallback covers
+                // clients that only render plain text. Mentions inside attachments don't
+                // trigger notifications, so they go in the top-level text.
+                let mentions: Vec<String> = notification.mentions.iter().map(Mention::slack).collect();
+                let payload = json!({
+                    "text": mentions.join(" "),
+                    "attachments": [{
+                        "color": colors.color(notification.severity),
+                        "text": notification.text,
+                        "fallback": notification.text,
+                        "mrkdwn_in": ["text"]
+                    }]
+                });
+
+                post_json(client, webhook, &payload, "Slack webhook").await
+            }
+            Notifier::PagerDuty { routing_key } => {
+                // A recovery resolves the incident opened for its URL; any other message triggers
+                // one, or updates the open incident of the same URL
+                let event = match &notification.incident {
+                    Some(incident) if incident.resolved => json!({
+                        "routing_key": routing_key,
+                        "event_action": "resolve",
+                        "dedup_key": incident.key,
+                    }),
+                    incident => {
+                        let mut event = json!({
+                            "routing_key": routing_key,
+                            "event_action": "trigger",
+                            "payload": {
+                                "summary": notification.text,
+                                "source": incident.as_ref().map_or("upnotif", |incident| incident.key.as_str()),
+                                "severity": notification.severity.to_string(),
+                            },
+                        });
+                        if let Some(incident) = incident {
+                            event["dedup_key"] = json!(incident.key);
+                        }
+                        event
+                    }
+                };
+
+                post_json(client, PAGERDUTY_EVENTS_URL, &event, "PagerDuty").await
+            }
+        }
+    }
+}
+
+/// Machine-readable description of a single status change.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusEvent {
+    pub url: String,
+    pub name: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub previous: String,
+    pub current: String,
+    pub timestamp: u64,
+    pub latency_ms: Option<u64>,
+}
+
+/// Generic webhook that receives one structured JSON event per status change.
+pub struct EventWebhook {
+    url: String,
+    throttle: Option<Throttle>,
+}
+
+impl EventWebhook {
+    pub fn new(url: String, throttle: Option<Throttle>) -> Self {
+        Self { url, throttle }
+    }
+
+    pub async fn send(&self, client: &Client, event: &StatusEvent) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(throttle) = &self.throttle {
+            throttle.acquire().map_err(|e| format!("Event webhook {}", e))?;
+        }
+        post_json(client, &self.url, event, "Event webhook").await
+    }
+}
+
+/// POSTs a JSON payload, retrying with a short backoff on errors and non-2xx responses.
+async fn post_json<T: Serialize + ?Sized>(
+    client: &Client,
+    url: &str,
+    payload: &T,
+    target: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut attempt = 1;
+
+    loop {
+        let error = match client.post(url).json(payload).send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => format!("{} returned status: {}", target, response.status()),
+            // The webhook URL is a credential, so it is kept out of errors and logs
+            Err(e) => format!("{} request failed: {}", target, e.without_url()),
+        };
+
+        if attempt >= DELIVERY_ATTEMPTS {
+            return Err(error.into());
+        }
+
+        warn!("{} (attempt {}/{}), retrying", error, attempt, DELIVERY_ATTEMPTS);
+        sleep(Duration::from_secs(u64::from(attempt))).await;
+        attempt += 1;
+    }
+}
@@ -0,0 +1,160 @@
+//! OAuth2 client-credentials tokens for health endpoints behind an authorization server.
+//!
+//! A token is fetched on first use, cached, and fetched again shortly before it expires, so checks
+//! carry a valid `Authorization: Bearer` header without manual rotation. URLs with the same client
+//! and token endpoint share one token.
+
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use std::env;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use url::Url;
+
+/// Lifetime assumed when the token response has no `expires_in`.
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(300);
+/// Refresh at most this long before expiry, so a check never goes out with a token about to lapse.
+const MAX_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// The `oauth` option of a URL in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OAuthConfig {
+    pub token_url: String,
+    pub client_id: String,
+    /// Environment variable holding the client secret, so it stays out of the config file.
+    pub client_secret_env: String,
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Requested audience, for providers such as Auth0 that need one.
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// Send the client credentials in the form body instead of HTTP Basic authentication.
+    #[serde(default)]
+    pub credentials_in_body: bool,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+struct CachedToken {
+    value: String,
+    refresh_at: Instant,
+}
+
+pub struct TokenProvider {
+    token_url: Url,
+    client_id: String,
+    client_secret: String,
+    scope: Option<String>,
+    audience: Option<String>,
+    credentials_in_body: bool,
+    /// Held across a fetch so concurrent checks wait for one token request instead of each sending their own.
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl std::fmt::Debug for TokenProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenProvider")
+            .field("token_url", &self.token_url.as_str())
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TokenProvider {
+    pub fn new(config: &OAuthConfig) -> Result<Self, String> {
+        let token_url = Url::parse(&config.token_url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| format!("token_url must be an http:// or https:// URL: {}", config.token_url))?;
+        let client_secret = env::var(&config.client_secret_env)
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .ok_or_else(|| format!("client secret {} is not set", config.client_secret_env))?;
+
+        Ok(Self {
+            token_url,
+            client_id: config.client_id.clone(),
+            client_secret,
+            scope: config.scope.clone(),
+            audience: config.audience.clone(),
+            credentials_in_body: config.credentials_in_body,
+            cached: Mutex::new(None),
+        })
+    }
+
+    /// Identifies providers that would fetch the same token.
+    pub fn key(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.token_url,
+            self.client_id,
+            self.scope.as_deref().unwrap_or_default(),
+            self.audience.as_deref().unwrap_or_default()
+        )
+    }
+
+    /// Adds a bearer token to the request, fetching a new one first if needed.
+    pub async fn authorize(&self, client: &Client, request: RequestBuilder) -> Result<RequestBuilder, String> {
+        let token = self.token(client).await?;
+        Ok(request.bearer_auth(token))
+    }
+
+    /// Drops the cached token, e.g. after the endpoint rejected it with `401`.
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    async fn token(&self, client: &Client) -> Result<String, String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref().filter(|token| Instant::now() < token.refresh_at) {
+            return Ok(token.value.clone());
+        }
+
+        let token = self.fetch(client).await?;
+        let value = token.value.clone();
+        *cached = Some(token);
+        Ok(value)
+    }
+
+    async fn fetch(&self, client: &Client) -> Result<CachedToken, String> {
+        let mut form = vec![("grant_type", "client_credentials")];
+        form.extend(self.scope.as_deref().map(|scope| ("scope", scope)));
+        form.extend(self.audience.as_deref().map(|audience| ("audience", audience)));
+        if self.credentials_in_body {
+            form.push(("client_id", &self.client_id));
+            form.push(("client_secret", &self.client_secret));
+        }
+
+        let mut request = client.post(self.token_url.clone()).form(&form);
+        if !self.credentials_in_body {
+            request = request.basic_auth(&self.client_id, Some(&self.client_secret));
+        }
+
+        let host = self.token_url.host_str().unwrap_or_default();
+        let response = request
+            .send()
+            .await
+            .map_err(|_| format!("token request to {} failed", host))?;
+        if !response.status().is_success() {
+            return Err(format!("token endpoint {} returned {}", host, response.status()));
+        }
+        let token: TokenResponse = response
+            .json()
+            .await
+            .map_err(|_| format!("token endpoint {} returned no access_token", host))?;
+
+        let lifetime = token.expires_in.map_or(DEFAULT_LIFETIME, Duration::from_secs);
+        let margin = (lifetime / 10).min(MAX_REFRESH_MARGIN);
+        Ok(CachedToken {
+            value: token.access_token,
+            refresh_at: Instant::now() + lifetime - margin,
+        })
+    }
+}
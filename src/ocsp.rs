@@ -0,0 +1,160 @@
+//! Checks whether a server staples an OCSP response to its TLS handshake, and what it says.
+//!
+//! The stapled response is captured by wrapping the normal certificate verifier, so the handshake
+//! is verified exactly as for any other check. Only the parts of the response needed to read the
+//! certificate status and its expiry are decoded; the responder's signature is not verified.
+
+use crate::tls::{self, Dialer};
+use chrono::NaiveDateTime;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio_rustls::rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use tokio_rustls::rustls::{self, Certificate, ServerName};
+use tokio_rustls::TlsConnector;
+
+const SEQUENCE: u8 = 0x30;
+const ENUMERATED: u8 = 0x0a;
+const OCTET_STRING: u8 = 0x04;
+const GENERALIZED_TIME: u8 = 0x18;
+/// Context-specific tag `[0]`, constructed; wraps explicitly tagged optional fields.
+const EXPLICIT_0: u8 = 0xa0;
+const CERT_STATUS_GOOD: u8 = 0x80;
+const CERT_STATUS_REVOKED: u8 = 0xa1;
+const CERT_STATUS_UNKNOWN: u8 = 0x82;
+
+/// What the server stapled to the handshake.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Stapling {
+    Good,
+    Revoked,
+    Missing,
+    /// A response was stapled but says nothing usable, e.g. it expired or the responder failed.
+    Unusable(String),
+}
+
+/// Records the stapled OCSP response of the handshake it verifies.
+struct StapleRecorder {
+    inner: WebPkiVerifier,
+    response: Mutex<Vec<u8>>,
+}
+
+impl ServerCertVerifier for StapleRecorder {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)?;
+        *self.response.lock().unwrap_or_else(|e| e.into_inner()) = ocsp_response.to_vec();
+        Ok(verified)
+    }
+}
+
+/// Performs a verified handshake with `host:port` and reports the stapled OCSP response.
+pub async fn check(host: &str, port: u16, dialer: &Dialer) -> Result<Stapling, String> {
+    let recorder = Arc::new(StapleRecorder {
+        inner: WebPkiVerifier::new(tls::root_store(), None),
+        response: Mutex::default(),
+    });
+    let mut config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(tls::root_store())
+        .with_no_client_auth();
+    config.dangerous().set_certificate_verifier(recorder.clone());
+
+    tls::connect(&TlsConnector::from(Arc::new(config)), host, port, dialer).await?;
+
+    let response = std::mem::take(&mut *recorder.response.lock().unwrap_or_else(|e| e.into_inner()));
+    if response.is_empty() {
+        return Ok(Stapling::Missing);
+    }
+    Ok(certificate_status(&response, chrono::Utc::now().naive_utc()).unwrap_or_else(Stapling::Unusable))
+}
+
+/// Reads the status of the first certificate in a DER-encoded `OCSPResponse` (RFC 6960).
+fn certificate_status(response: &[u8], now: NaiveDateTime) -> Result<Stapling, String> {
+    let (ocsp, _) = expect(response, SEQUENCE)?;
+    let (status, rest) = expect(ocsp, ENUMERATED)?;
+    if status != [0] {
+        return Err(format!("OCSP responder returned error status {}", status.first().copied().unwrap_or_default()));
+    }
+
+    // responseBytes: the type OID, then the BasicOCSPResponse in an OCTET STRING
+    let (response_bytes, _) = expect(rest, EXPLICIT_0)?;
+    let (response_bytes, _) = expect(response_bytes, SEQUENCE)?;
+    let (_, _, rest) = next(response_bytes).ok_or_else(malformed)?;
+    let (basic, _) = expect(rest, OCTET_STRING)?;
+    let (basic, _) = expect(basic, SEQUENCE)?;
+    let (data, _) = expect(basic, SEQUENCE)?;
+
+    // ResponseData: optional version, responder ID and production time precede the responses
+    let mut rest = data;
+    if let Some((EXPLICIT_0, _, after)) = next(rest) {
+        rest = after;
+    }
+    let (_, _, rest) = next(rest).ok_or_else(malformed)?;
+    let (_, _, rest) = next(rest).ok_or_else(malformed)?;
+    let (responses, _) = expect(rest, SEQUENCE)?;
+    let (single, _) = expect(responses, SEQUENCE)?;
+
+    let (_, _, rest) = next(single).ok_or_else(malformed)?;
+    let (cert_status, _, rest) = next(rest).ok_or_else(malformed)?;
+    let (_, rest) = expect(rest, GENERALIZED_TIME)?;
+
+    match cert_status {
+        CERT_STATUS_GOOD => {}
+        CERT_STATUS_REVOKED => return Ok(Stapling::Revoked),
+        CERT_STATUS_UNKNOWN => return Err("OCSP responder does not know the certificate".to_string()),
+        _ => return Err(malformed()),
+    }
+
+    if let Some((EXPLICIT_0, next_update, _)) = next(rest) {
+        let (next_update, _) = expect(next_update, GENERALIZED_TIME)?;
+        let next_update = generalized_time(next_update).ok_or_else(malformed)?;
+        if next_update < now {
+            return Err(format!("stapled OCSP response expired at {} UTC", next_update.format("%Y-%m-%d %H:%M:%S")));
+        }
+    }
+    Ok(Stapling::Good)
+}
+
+fn malformed() -> String {
+    "malformed OCSP response".to_string()
+}
+
+/// Splits the next DER element off `input` as its tag, contents and the remaining input.
+fn next(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let (len, rest) = rest.split_at(count);
+        (len.iter().fold(0, |len, byte| len << 8 | usize::from(*byte)), rest)
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// Like `next`, but fails unless the element has the given tag.
+fn expect(input: &[u8], tag: u8) -> Result<(&[u8], &[u8]), String> {
+    match next(input) {
+        Some((found, contents, rest)) if found == tag => Ok((contents, rest)),
+        _ => Err(malformed()),
+    }
+}
+
+/// Parses a `GeneralizedTime` such as `20240131150500Z`, ignoring fractional seconds.
+fn generalized_time(value: &[u8]) -> Option<NaiveDateTime> {
+    let value = std::str::from_utf8(value).ok()?.strip_suffix('Z')?;
+    NaiveDateTime::parse_from_str(value.get(..14)?, "%Y%m%d%H%M%S").ok()
+}
@@ -0,0 +1,491 @@
+//! OpenTelemetry traces of the checks, exported to a collector with OTLP over HTTP, encoded as
+//! protobuf or JSON.
+//!
+//! Every check of a URL becomes one client span. Its trace context is sent with the check requests
+//! in a W3C `traceparent` header, so spans the server records for those requests join the same
+//! trace. Spans are batched and exported in the background, and are dropped rather than holding up
+//! checks when the collector is slow or unreachable.
+//!
+//! The exporter is configured with the standard `OTEL_*` environment variables and is only enabled
+//! when an OTLP endpoint is set.
+//!
+//! The few OTLP messages needed are encoded here rather than with the `opentelemetry` crates. The
+//! monitor logs through `log` and has no `tracing` spans to bridge, and one span per check doesn't
+//! justify a second telemetry stack with prost and its own exporter client in the static binary.
+
+use log::{info, warn};
+use percent_encoding::percent_decode_str;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::time::{timeout_at, Instant};
+use url::Url;
+
+/// Largest number of spans sent in one export request.
+const MAX_BATCH: usize = 512;
+/// Spans finished while this many are waiting for export are dropped.
+const QUEUED_SPANS: usize = 2048;
+/// How long a finished span waits for others to share its export request.
+const BATCH_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+tokio::task_local! {
+    static CURRENT: Arc<CheckTrace>;
+}
+
+/// Where and how spans are exported, read from the `OTEL_*` environment variables.
+#[derive(Clone)]
+pub struct OtlpConfig {
+    pub endpoint: Url,
+    protocol: Protocol,
+    headers: HeaderMap,
+    timeout: Duration,
+    service_name: String,
+    resource_attributes: Vec<(String, String)>,
+}
+
+impl OtlpConfig {
+    /// Returns `None` unless an OTLP endpoint is set and the SDK is not disabled.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        if env_value("OTEL_SDK_DISABLED").is_some_and(|value| value.eq_ignore_ascii_case("true")) {
+            return Ok(None);
+        }
+        match env_value("OTEL_TRACES_EXPORTER").as_deref() {
+            None | Some("otlp") => {}
+            Some("none") => return Ok(None),
+            Some(_) => return Err("OTEL_TRACES_EXPORTER must be otlp or none".to_string()),
+        }
+
+        let endpoint = match (
+            env_value("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"),
+            env_value("OTEL_EXPORTER_OTLP_ENDPOINT"),
+        ) {
+            (Some(endpoint), _) => endpoint,
+            (None, Some(base)) => format!("{}/v1/traces", base.trim_end_matches('/')),
+            (None, None) => return Ok(None),
+        };
+        let endpoint = Url::parse(&endpoint)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| format!("OTLP endpoint must be an http:// or https:// URL: {}", endpoint))?;
+
+        let protocol = match ["OTEL_EXPORTER_OTLP_TRACES_PROTOCOL", "OTEL_EXPORTER_OTLP_PROTOCOL"]
+            .into_iter()
+            .find_map(|name| env_value(name).map(|protocol| (name, protocol)))
+        {
+            None => Protocol::Protobuf,
+            Some((_, protocol)) if protocol == "http/protobuf" => Protocol::Protobuf,
+            Some((_, protocol)) if protocol == "http/json" => Protocol::Json,
+            Some((name, protocol)) if protocol == "grpc" => {
+                return Err(format!("{} is grpc, which is not supported; use http/protobuf or http/json", name));
+            }
+            Some((name, _)) => return Err(format!("{} must be http/protobuf or http/json", name)),
+        };
+
+        let mut headers = HeaderMap::new();
+        for name in ["OTEL_EXPORTER_OTLP_HEADERS", "OTEL_EXPORTER_OTLP_TRACES_HEADERS"] {
+            for (key, value) in key_value_list(name)? {
+                let key = HeaderName::from_bytes(key.as_bytes())
+                    .map_err(|_| format!("{} has an invalid header name: {}", name, key))?;
+                let value =
+                    HeaderValue::from_str(&value).map_err(|_| format!("{} has an invalid value for {}", name, key))?;
+                headers.insert(key, value);
+            }
+        }
+
+        let timeout = env_value("OTEL_EXPORTER_OTLP_TRACES_TIMEOUT").or_else(|| env_value("OTEL_EXPORTER_OTLP_TIMEOUT"));
+        let timeout = match timeout {
+            Some(millis) => millis
+                .parse()
+                .ok()
+                .filter(|millis| *millis > 0)
+                .map(Duration::from_millis)
+                .ok_or("OTEL_EXPORTER_OTLP_TIMEOUT must be a positive number of milliseconds")?,
+            None => DEFAULT_TIMEOUT,
+        };
+
+        let mut resource_attributes = key_value_list("OTEL_RESOURCE_ATTRIBUTES")?;
+        let service_name = env_value("OTEL_SERVICE_NAME")
+            .or_else(|| {
+                resource_attributes
+                    .iter()
+                    .find(|(key, _)| key == "service.name")
+                    .map(|(_, value)| value.clone())
+            })
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+        resource_attributes.retain(|(key, _)| key != "service.name");
+
+        Ok(Some(Self {
+            endpoint,
+            protocol,
+            headers,
+            timeout,
+            service_name,
+            resource_attributes,
+        }))
+    }
+}
+
+/// How export requests are encoded, from `OTEL_EXPORTER_OTLP_PROTOCOL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Protobuf,
+    Json,
+}
+
+fn env_value(name: &str) -> Option<String> {
+    env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// Parses a `key1=value1,key2=value2` list, whose values may be percent-encoded.
+fn key_value_list(name: &str) -> Result<Vec<(String, String)>, String> {
+    let Some(list) = env_value(name) else {
+        return Ok(Vec::new());
+    };
+    list.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (key, value) = pair
+                .split_once('=')
+                .filter(|(key, _)| !key.trim().is_empty())
+                .ok_or_else(|| format!("{} must be a comma-separated list of key=value pairs", name))?;
+            let value = percent_decode_str(value.trim()).decode_utf8_lossy();
+            Ok((key.trim().to_string(), value.into_owned()))
+        })
+        .collect()
+}
+
+/// Identifies a span within its trace, as sent to the server in `traceparent`.
+#[derive(Debug, Clone, Copy)]
+pub struct SpanContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+}
+
+impl SpanContext {
+    /// Starts a new trace.
+    fn new() -> Self {
+        Self {
+            trace_id: rand::random(),
+            span_id: rand::random(),
+        }
+    }
+
+    /// The W3C `traceparent` header value, marking the trace as sampled.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-01", hex(&self.trace_id), hex(&self.span_id))
+    }
+}
+
+/// What the requests of the check running in the current task contribute to its span.
+#[derive(Debug)]
+pub struct CheckTrace {
+    pub context: SpanContext,
+    /// Correlation ID of the last request sent, when request IDs are enabled.
+    request_id: Mutex<Option<String>>,
+}
+
+impl CheckTrace {
+    /// Runs `check` with a new trace that requests made during it can read through `current`.
+    pub async fn scope<F: std::future::Future>(check: F) -> (F::Output, Arc<CheckTrace>) {
+        let trace = Arc::new(CheckTrace {
+            context: SpanContext::new(),
+            request_id: Mutex::default(),
+        });
+        let output = CURRENT.scope(trace.clone(), check).await;
+        (output, trace)
+    }
+
+    /// The trace of the check running in the current task, if it is traced.
+    pub fn current() -> Option<Arc<CheckTrace>> {
+        CURRENT.try_with(Arc::clone).ok()
+    }
+
+    pub fn set_request_id(&self, id: &str) {
+        *self.request_id.lock().unwrap_or_else(|e| e.into_inner()) = Some(id.to_string());
+    }
+
+    pub fn request_id(&self) -> Option<String> {
+        self.request_id.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Int(value)
+    }
+}
+
+/// A finished span.
+#[derive(Debug)]
+pub struct Span {
+    pub context: SpanContext,
+    pub name: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(&'static str, AttributeValue)>,
+    /// Set for a failed check, with its reason.
+    pub error: Option<String>,
+}
+
+impl Span {
+    /// The span in OTLP/JSON.
+    fn to_json(&self) -> Value {
+        let attributes: Vec<Value> = self.attributes.iter().map(|(key, value)| attribute(key, value)).collect();
+        let status = match &self.error {
+            Some(message) => json!({ "code": 2, "message": message }),
+            None => json!({ "code": 1 }),
+        };
+        json!({
+            "traceId": hex(&self.context.trace_id),
+            "spanId": hex(&self.context.span_id),
+            "name": self.name,
+            // SPAN_KIND_CLIENT
+            "kind": 3,
+            // 64-bit integers are strings in OTLP/JSON
+            "startTimeUnixNano": unix_nanos(self.start).to_string(),
+            "endTimeUnixNano": unix_nanos(self.end).to_string(),
+            "attributes": attributes,
+            "status": status,
+        })
+    }
+
+    /// The span as an OTLP `Span` message.
+    fn to_protobuf(&self) -> Message {
+        let mut span = Message::default();
+        span.bytes(1, &self.context.trace_id);
+        span.bytes(2, &self.context.span_id);
+        span.string(5, &self.name);
+        // SPAN_KIND_CLIENT
+        span.uint(6, 3);
+        span.fixed64(7, unix_nanos(self.start));
+        span.fixed64(8, unix_nanos(self.end));
+        for (key, value) in &self.attributes {
+            span.message(9, attribute_protobuf(key, value));
+        }
+        let mut status = Message::default();
+        match &self.error {
+            Some(message) => {
+                status.string(2, message);
+                status.uint(3, 2);
+            }
+            None => status.uint(3, 1),
+        }
+        span.message(15, status);
+        span
+    }
+}
+
+fn attribute(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::String(value) => json!({ "stringValue": value }),
+        // 64-bit integers are strings in OTLP/JSON
+        AttributeValue::Int(value) => json!({ "intValue": value.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn attribute_protobuf(key: &str, value: &AttributeValue) -> Message {
+    let mut any = Message::default();
+    match value {
+        AttributeValue::String(value) => any.string(1, value),
+        AttributeValue::Int(value) => any.int(3, *value),
+    }
+    let mut attribute = Message::default();
+    attribute.string(1, key);
+    attribute.message(2, any);
+    attribute
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+/// A protobuf message being encoded, with only the field types the OTLP messages use.
+#[derive(Debug, Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(u64::from(field << 3 | wire_type));
+    }
+
+    /// An integer or enum field; zero is the default and is left out, as protobuf encoders do.
+    fn uint(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, 0);
+            self.varint(value);
+        }
+    }
+
+    /// An `int64` field that is part of a `oneof`, so it is written even when zero.
+    fn int(&mut self, field: u32, value: i64) {
+        self.key(field, 0);
+        self.varint(value as u64);
+    }
+
+    fn fixed64(&mut self, field: u32, value: u64) {
+        self.key(field, 1);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, 2);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u32, message: Message) {
+        self.bytes(field, &message.0);
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Queues finished spans for the background exporter.
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    sender: mpsc::Sender<Span>,
+}
+
+impl Tracer {
+    /// Starts exporting spans to the configured collector in the background.
+    pub fn start(config: OtlpConfig, client: Client) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUED_SPANS);
+        tokio::spawn(export(config, client, receiver));
+        Self { sender }
+    }
+
+    /// Queues a span for export, dropping it if the exporter is too far behind.
+    pub fn record(&self, span: Span) {
+        let _ = self.sender.try_send(span);
+    }
+}
+
+async fn export(config: OtlpConfig, client: Client, mut receiver: mpsc::Receiver<Span>) {
+    let mut resource: Vec<(&str, AttributeValue)> = vec![("service.name", config.service_name.as_str().into())];
+    resource.extend(
+        config
+            .resource_attributes
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str().into())),
+    );
+    let mut failing = false;
+
+    while let Some(first) = receiver.recv().await {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + BATCH_DELAY;
+        while batch.len() < MAX_BATCH {
+            match timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(span)) => batch.push(span),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let (content_type, body) = match config.protocol {
+            Protocol::Protobuf => ("application/x-protobuf", encode_protobuf(&resource, &batch)),
+            Protocol::Json => ("application/json", encode_json(&resource, &batch)),
+        };
+        let result = client
+            .post(config.endpoint.clone())
+            .headers(config.headers.clone())
+            .header(CONTENT_TYPE, content_type)
+            .timeout(config.timeout)
+            .body(body)
+            .send()
+            .await;
+
+        // Only the first failure and the recovery are logged, not every export in between
+        match result.map(|response| response.status()) {
+            Ok(status) if status.is_success() && failing => {
+                info!("Exporting traces to {} works again", config.endpoint);
+                failing = false;
+            }
+            Ok(status) if status.is_success() => {}
+            Ok(status) if !failing => {
+                warn!("Failed to export {} spans to {}: {}", batch.len(), config.endpoint, status);
+                failing = true;
+            }
+            Err(e) if !failing => {
+                warn!("Failed to export {} spans to {}: {}", batch.len(), config.endpoint, e);
+                failing = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// An `ExportTraceServiceRequest` in OTLP/JSON.
+fn encode_json(resource: &[(&str, AttributeValue)], spans: &[Span]) -> Vec<u8> {
+    let attributes: Vec<Value> = resource.iter().map(|(key, value)| attribute(key, value)).collect();
+    let spans: Vec<Value> = spans.iter().map(Span::to_json).collect();
+    let body = json!({
+        "resourceSpans": [{
+            "resource": { "attributes": attributes },
+            "scopeSpans": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    });
+    body.to_string().into_bytes()
+}
+
+/// An `ExportTraceServiceRequest` in the OTLP protobuf encoding.
+fn encode_protobuf(resource: &[(&str, AttributeValue)], spans: &[Span]) -> Vec<u8> {
+    let mut resource_message = Message::default();
+    for (key, value) in resource {
+        resource_message.message(1, attribute_protobuf(key, value));
+    }
+    let mut scope = Message::default();
+    scope.string(1, env!("CARGO_PKG_NAME"));
+    scope.string(2, env!("CARGO_PKG_VERSION"));
+    let mut scope_spans = Message::default();
+    scope_spans.message(1, scope);
+    for span in spans {
+        scope_spans.message(2, span.to_protobuf());
+    }
+
+    let mut resource_spans = Message::default();
+    resource_spans.message(1, resource_message);
+    resource_spans.message(2, scope_spans);
+    let mut request = Message::default();
+    request.message(1, resource_spans);
+    request.0
+}
@@ -0,0 +1,65 @@
+//! Undelivered notifications persisted across restarts, so alerts queued during a notifier outage
+//! are still delivered if the monitor restarts before the notifier recovers.
+
+use crate::snapshot::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the file layout changes incompatibly; other versions are not loaded.
+pub const OUTBOX_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistedOutbox {
+    pub version: u32,
+    pub saved_at: u64,
+    /// Messages already dropped because the queue was full, still to be reported.
+    pub dropped: usize,
+    /// Oldest first, in delivery order.
+    pub messages: Vec<PersistedNotification>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedNotification {
+    /// Name of the route the message goes to, or `None` for `UPNOTIF_SLACK_WEBHOOK`.
+    pub route: Option<String>,
+    pub text: String,
+    pub severity: String,
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    /// URL of the incident the message triggers or resolves on a PagerDuty route.
+    #[serde(default)]
+    pub incident: Option<String>,
+    #[serde(default)]
+    pub resolved: bool,
+}
+
+/// Reads the queue file, returning `None` if it doesn't exist yet.
+pub fn load(path: &Path) -> Result<Option<PersistedOutbox>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let outbox: PersistedOutbox = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+    if outbox.version != OUTBOX_VERSION {
+        return Err(format!("unsupported queue file version {}", outbox.version));
+    }
+    Ok(Some(outbox))
+}
+
+pub fn save(path: &Path, outbox: &PersistedOutbox) -> io::Result<()> {
+    write_atomic(path, &serde_json::to_vec(outbox)?)
+}
+
+/// Moves an unloadable queue file to `<path>.corrupt`, so it can be inspected instead of being
+/// overwritten by the next save.
+pub fn quarantine(path: &Path) -> io::Result<PathBuf> {
+    let mut corrupt_name = path.as_os_str().to_owned();
+    corrupt_name.push(".corrupt");
+    let corrupt_path = PathBuf::from(corrupt_name);
+    fs::rename(path, &corrupt_path)?;
+    Ok(corrupt_path)
+}
@@ -0,0 +1,183 @@
+//! Where each configuration value came from, reported at startup with `UPNOTIF_DEBUG_CONFIG`.
+//!
+//! Global settings only come from `UPNOTIF_*` environment variables, falling back to built-in
+//! defaults. URLs come from `UPNOTIF_URLS` and the config file, and a profile overlay replaces
+//! values of the config file. Settings are read through `var` so the report can tell variables that
+//! were set from defaults, and point out `UPNOTIF_*` variables that are set but were never used.
+
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env::{self, VarError};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use url::Url;
+
+/// Variables that are not runtime settings, so being set but unread is expected.
+const NOT_SETTINGS: &[&str] = &["UPNOTIF_GIT_SHA"];
+
+/// Every setting read so far, with its value when it was set.
+static READS: Mutex<BTreeMap<&'static str, Option<String>>> = Mutex::new(BTreeMap::new());
+
+/// Reads a setting like `env::var`, remembering it for the report.
+pub fn var(name: &'static str) -> Result<String, VarError> {
+    let value = env::var(name);
+    let mut reads = READS.lock().unwrap_or_else(|e| e.into_inner());
+    reads.insert(name, value.as_ref().ok().cloned());
+    value
+}
+
+/// A value the profile overlay set in the config file.
+#[derive(Debug)]
+pub struct OverlayChange {
+    /// Location in the config file, such as `urls` or `host_delays_ms.example.com`.
+    pub path: String,
+    /// The base file's value, if it had one.
+    pub previous: Option<Value>,
+    /// `None` when the overlay removed the value with `null`.
+    pub value: Option<Value>,
+}
+
+/// The config file and profile overlay that were merged, and what the overlay changed.
+#[derive(Debug)]
+pub struct FileSources {
+    pub base: PathBuf,
+    pub overlay: Option<PathBuf>,
+    pub changes: Vec<OverlayChange>,
+    /// The merged config file.
+    pub effective: Value,
+}
+
+impl FileSources {
+    /// Whether the value at `path` came from the overlay rather than the base file.
+    pub fn set_by_overlay(&self, path: &str) -> bool {
+        self.changes.iter().any(|change| {
+            path.strip_prefix(change.path.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.') || rest.starts_with('['))
+        })
+    }
+
+    fn source(&self, path: &str) -> String {
+        match &self.overlay {
+            Some(overlay) if self.set_by_overlay(path) => overlay.display().to_string(),
+            _ => self.base.display().to_string(),
+        }
+    }
+}
+
+/// Appends `key` to a config file path.
+pub fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Logs every setting with its source, what the profile overlay changed, every config file value
+/// with the file it came from, and where each monitored URL was configured.
+pub fn report(files: Option<&FileSources>, urls: &[(String, String)]) {
+    info!("Effective config (UPNOTIF_DEBUG_CONFIG), environment variables:");
+    let reads = READS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    for (name, value) in &reads {
+        if let Some(value) = value {
+            info!("  {} = {} (environment)", name, display_setting(name, value));
+        }
+    }
+    let defaults: Vec<&str> = reads.iter().filter(|(_, value)| value.is_none()).map(|(name, _)| *name).collect();
+    if !defaults.is_empty() {
+        info!("  Not set, using the defaults: {}", defaults.join(", "));
+    }
+    for (name, _) in env::vars() {
+        let unused = !reads.contains_key(name.as_str()) && !NOT_SETTINGS.contains(&name.as_str());
+        if name.starts_with("UPNOTIF_") && unused {
+            warn!("  {} is set but was not used; it is misspelled or has no effect with this config", name);
+        }
+    }
+
+    if let Some(files) = files {
+        match &files.overlay {
+            Some(overlay) => {
+                info!("Config file {} with profile overlay {}:", files.base.display(), overlay.display());
+                for change in &files.changes {
+                    let key = last_key(&change.path);
+                    let to = change.value.as_ref().map_or_else(|| "removed".to_string(), |value| redacted(key, value));
+                    match &change.previous {
+                        Some(previous) => info!("  {} overridden: {} -> {}", change.path, redacted(key, previous), to),
+                        None => info!("  {} added: {}", change.path, to),
+                    }
+                }
+            }
+            None => info!("Config file {}:", files.base.display()),
+        }
+        let mut values = Vec::new();
+        leaves(&files.effective, String::new(), &mut values);
+        for (path, value) in values {
+            info!("  {} = {} ({})", path, redacted(last_key(&path), value), files.source(&path));
+        }
+    }
+
+    info!("Monitored URLs:");
+    for (url, source) in urls {
+        info!("  {} ({})", url, source);
+    }
+}
+
+/// Flattens objects, and lists of objects such as `urls`, into one value per path.
+fn leaves<'a>(value: &'a Value, path: String, out: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                leaves(value, join(&path, key), out);
+            }
+        }
+        Value::Array(items) if items.iter().any(Value::is_object) => {
+            for (index, item) in items.iter().enumerate() {
+                leaves(item, format!("{}[{}]", path, index), out);
+            }
+        }
+        _ => out.push((path, value)),
+    }
+}
+
+fn last_key(path: &str) -> &str {
+    path.rsplit(['.', ']']).next().unwrap_or_default()
+}
+
+/// Renders a config file value with the values of secret keys, at any depth, hidden.
+fn redacted(key: &str, value: &Value) -> String {
+    fn hide(key: &str, value: &Value) -> Value {
+        match value {
+            _ if is_secret(key) => Value::String("<redacted>".to_string()),
+            Value::Object(map) => {
+                Value::Object(map.iter().map(|(key, value)| (key.clone(), hide(key, value))).collect())
+            }
+            Value::Array(items) => Value::Array(items.iter().map(|item| hide("", item)).collect()),
+            value => value.clone(),
+        }
+    }
+    hide(key, value).to_string()
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["webhook", "token", "secret", "password", "routing_key"]
+        .iter()
+        .any(|word| name.contains(word))
+        && !name.ends_with("_env")
+        && !name.ends_with("_url")
+}
+
+/// Hides secrets, and passwords in URLs such as a proxy's.
+fn display_setting(name: &str, value: &str) -> String {
+    if is_secret(name) {
+        return "<redacted>".to_string();
+    }
+    match Url::parse(value) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("***"));
+            url.to_string()
+        }
+        _ => value.to_string(),
+    }
+}
@@ -0,0 +1,75 @@
+//! Routing of status changes to additional Slack channels or PagerDuty services based on URL tags
+//! and an optional schedule, such as business hours or an on-call rotation.
+//!
+//! Routes are additive: a change goes to every route whose tags match and whose schedule is
+//! active, and only changes that match no route go to the default `UPNOTIF_SLACK_WEBHOOK`.
+
+use crate::rules;
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use url::Url;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Route {
+    /// Label used in logs, typically the channel name such as `#payments-oncall`.
+    pub name: String,
+    /// Slack incoming webhook of the channel.
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// PagerDuty Events API v2 integration key, to page instead of posting to Slack.
+    #[serde(default)]
+    pub routing_key: Option<String>,
+    /// Every listed tag must be present on the URL with the same value; empty matches every URL.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Days of the week the route is active, e.g. `["mon", "tue"]`; empty means every day.
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// Time range the route is active in `UPNOTIF_TIMEZONE`, e.g. `"09:00-17:00"`; may wrap past midnight.
+    #[serde(default)]
+    pub hours: Option<String>,
+}
+
+impl Route {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Route name must not be empty".to_string());
+        }
+        match (&self.webhook, &self.routing_key) {
+            (Some(webhook), None) => {
+                Url::parse(webhook).map_err(|_| format!("Invalid webhook URL for route '{}'", self.name))?;
+            }
+            (None, Some(routing_key)) if routing_key.trim().is_empty() => {
+                return Err(format!("Route '{}' has an empty routing_key", self.name));
+            }
+            (None, Some(_)) => {}
+            _ => return Err(format!("Route '{}' needs exactly one of webhook and routing_key", self.name)),
+        }
+        rules::validate_schedule(&self.days, self.hours.as_deref(), &format!("route '{}'", self.name))
+    }
+
+    pub fn matches(&self, tags: &BTreeMap<String, String>) -> bool {
+        self.tags.iter().all(|(key, value)| tags.get(key) == Some(value))
+    }
+
+    /// Whether the route only applies at some times.
+    pub fn is_scheduled(&self) -> bool {
+        !self.days.is_empty() || self.hours.is_some()
+    }
+
+    pub fn is_active(&self, now: u64, zone: Tz) -> bool {
+        rules::in_schedule(&self.days, self.hours.as_deref(), now, zone)
+    }
+}
+
+/// Indices of all routes matching the given tags and active at `now` in `zone`, in configuration order.
+pub fn matching_routes(routes: &[Route], tags: &BTreeMap<String, String>, now: u64, zone: Tz) -> Vec<usize> {
+    routes
+        .iter()
+        .enumerate()
+        .filter(|(_, route)| route.matches(tags) && route.is_active(now, zone))
+        .map(|(index, _)| index)
+        .collect()
+}
@@ -0,0 +1,136 @@
+//! Rule evaluation deciding whether a status transition produces a notification.
+//!
+//! Rules are checked in order and the first one that matches decides; a transition that
+//! matches no rule is notified.
+
+use chrono::{DateTime, Datelike, Timelike};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Allow,
+    Suppress,
+}
+
+/// Direction of a status change a rule applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transition {
+    /// The URL went down.
+    Down,
+    /// The URL recovered.
+    Up,
+    /// The URL became slow but is still responding.
+    Degraded,
+    /// The URL started answering with 429 Too Many Requests.
+    Throttled,
+    /// The URL's response time jumped far above its learned baseline.
+    Anomaly,
+    /// The content of a URL watched for changes changed.
+    Changed,
+    #[default]
+    Any,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub action: RuleAction,
+    /// Every listed tag must be present on the URL with the same value.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub transition: Transition,
+    /// Days of the week the rule is active, e.g. `["mon", "tue"]`; empty means every day.
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// Time range the rule is active in UTC, e.g. `"09:00-17:00"`; may wrap past midnight.
+    #[serde(default)]
+    pub hours: Option<String>,
+}
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+impl AlertRule {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_schedule(&self.days, self.hours.as_deref(), "alert rule")
+    }
+
+    fn matches(&self, tags: &BTreeMap<String, String>, transition: Transition, now: u64) -> bool {
+        if self.transition != Transition::Any && self.transition != transition {
+            return false;
+        }
+
+        if !self.tags.iter().all(|(key, value)| tags.get(key) == Some(value)) {
+            return false;
+        }
+
+        in_schedule(&self.days, self.hours.as_deref(), now, Tz::UTC)
+    }
+}
+
+/// Checks the `days` and `hours` of an alert rule or route; `context` names it in errors.
+pub fn validate_schedule(days: &[String], hours: Option<&str>, context: &str) -> Result<(), String> {
+    for day in days {
+        if !DAY_NAMES.contains(&day.to_lowercase().as_str()) {
+            return Err(format!("Invalid day '{}' in {}, expected one of {:?}", day, context, DAY_NAMES));
+        }
+    }
+    if let Some(hours) = hours {
+        parse_hours(hours).ok_or_else(|| format!("Invalid hours '{}' in {}, expected HH:MM-HH:MM", hours, context))?;
+    }
+    Ok(())
+}
+
+/// Whether `now` falls on one of `days` and within `hours` in `zone`; either being unset matches any time.
+///
+/// Ranges include their start but not their end, so at 17:00 exactly one of `09:00-17:00` and
+/// `17:00-09:00` matches. The part of a range after midnight belongs to the day it started on, so
+/// `["fri"]` with `22:00-06:00` is active from Friday 22:00 to Saturday 06:00.
+pub fn in_schedule(days: &[String], hours: Option<&str>, now: u64, zone: Tz) -> bool {
+    let (weekday, minute) = local_weekday_and_minute(now, zone);
+
+    let started_on = match hours.map(parse_hours) {
+        Some(Some((start, end))) if start <= end && (start..end).contains(&minute) => weekday,
+        Some(Some((start, end))) if start > end && minute >= start => weekday,
+        Some(Some((start, end))) if start > end && minute < end => (weekday + 6) % 7,
+        Some(Some(_)) => return false,
+        _ => weekday,
+    };
+
+    days.is_empty() || days.iter().any(|day| day.eq_ignore_ascii_case(DAY_NAMES[started_on]))
+}
+
+/// Returns whether a transition should be notified according to the first matching rule.
+pub fn should_notify(rules: &[AlertRule], tags: &BTreeMap<String, String>, transition: Transition, now: u64) -> bool {
+    rules
+        .iter()
+        .find(|rule| rule.matches(tags, transition, now))
+        .is_none_or(|rule| rule.action == RuleAction::Allow)
+}
+
+/// Parses `HH:MM-HH:MM` into start and end minutes of the day.
+fn parse_hours(hours: &str) -> Option<(u32, u32)> {
+    let (start, end) = hours.split_once('-')?;
+    Some((parse_time_of_day(start.trim())?, parse_time_of_day(end.trim())?))
+}
+
+fn parse_time_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    // 24:00 is allowed as an end-of-day marker
+    if minutes >= 60 || hours > 24 || (hours == 24 && minutes != 0) {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+/// Day of the week (Monday = 0) and minute of the day for a Unix timestamp in `zone`.
+fn local_weekday_and_minute(timestamp: u64, zone: Tz) -> (usize, u32) {
+    let time = DateTime::from_timestamp(timestamp as i64, 0).unwrap_or_default().with_timezone(&zone);
+    (time.weekday().num_days_from_monday() as usize, time.hour() * 60 + time.minute())
+}
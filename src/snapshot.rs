@@ -0,0 +1,72 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever a field is removed or changes meaning; new fields may be added freely.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Where the per-cycle state snapshot is written.
+#[derive(Debug, Clone)]
+pub enum SnapshotTarget {
+    Stdout,
+    File(PathBuf),
+}
+
+impl SnapshotTarget {
+    /// `-` selects stdout, anything else is a file path.
+    pub fn parse(value: &str) -> Self {
+        if value == "-" {
+            SnapshotTarget::Stdout
+        } else {
+            SnapshotTarget::File(PathBuf::from(value))
+        }
+    }
+
+    pub fn write(&self, snapshot: &Snapshot) -> io::Result<()> {
+        match self {
+            SnapshotTarget::Stdout => {
+                let line = serde_json::to_string(snapshot)?;
+                let mut stdout = io::stdout().lock();
+                writeln!(stdout, "{}", line)?;
+                stdout.flush()
+            }
+            SnapshotTarget::File(path) => write_atomic(path, &serde_json::to_vec_pretty(snapshot)?),
+        }
+    }
+}
+
+/// Writes to a sibling temp file and renames it into place so readers never see a partial file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Snapshot<'a> {
+    pub version: u32,
+    pub generated_at: u64,
+    pub urls: Vec<UrlSnapshot<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UrlSnapshot<'a> {
+    pub url: &'a str,
+    pub name: Option<&'a str>,
+    pub tags: &'a BTreeMap<String, String>,
+    pub status: String,
+    pub reason: Option<&'a str>,
+    pub last_checked: u64,
+    /// Response time of the last check if it succeeded; failed checks have no meaningful latency.
+    pub latency_ms: Option<u64>,
+    pub checks: u64,
+    pub failed_checks: u64,
+    pub uptime_percent: f64,
+    /// When the URL's deploy window ends, while notifications are suppressed by one.
+    pub deploy_window_until: Option<u64>,
+}
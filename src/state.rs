@@ -0,0 +1,58 @@
+//! Status persisted across restarts so a restart doesn't re-announce every URL.
+
+use crate::digest::DigestWindow;
+use crate::snapshot::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Bumped whenever the file layout changes incompatibly; other versions are ignored on load.
+pub const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistedState {
+    pub version: u32,
+    pub saved_at: u64,
+    pub urls: BTreeMap<String, PersistedUrl>,
+    /// The digest window in progress, saved with `UPNOTIF_DIGEST_RESUME`.
+    #[serde(default)]
+    pub digest: Option<PersistedDigest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedUrl {
+    pub status: String,
+    pub reason: Option<String>,
+    pub last_checked: u64,
+    pub checks: u64,
+    pub up_checks: u64,
+    #[serde(default)]
+    pub down_since: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedDigest {
+    pub started_at: u64,
+    pub urls: BTreeMap<String, DigestWindow>,
+}
+
+/// Reads the state file, returning `None` if it doesn't exist yet.
+pub fn load(path: &Path) -> Result<Option<PersistedState>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let state: PersistedState = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+    if state.version != STATE_VERSION {
+        return Err(format!("unsupported state file version {}", state.version));
+    }
+    Ok(Some(state))
+}
+
+pub fn save(path: &Path, state: &PersistedState) -> io::Result<()> {
+    write_atomic(path, &serde_json::to_vec(state)?)
+}
@@ -0,0 +1,142 @@
+//! TLS helpers shared by checks that open their own connections instead of going through reqwest.
+
+use crate::dns::Resolver;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::time::timeout;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{self, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+/// The bundled web PKI roots.
+pub fn root_store() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    roots
+}
+
+/// Builds a connector that trusts the bundled web PKI roots and offers the given ALPN protocols.
+pub fn connector(alpn_protocols: &[&[u8]]) -> TlsConnector {
+    let mut config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store())
+        .with_no_client_auth();
+    config.alpn_protocols = alpn_protocols.iter().map(|protocol| protocol.to_vec()).collect();
+
+    TlsConnector::from(Arc::new(config))
+}
+
+/// How checks that bypass reqwest open their connections.
+#[derive(Debug, Clone)]
+pub struct Dialer {
+    /// Covers name resolution, TCP and, where used, the TLS handshake.
+    pub connect_timeout: Duration,
+    /// Source address of outbound connections.
+    pub local_address: Option<IpAddr>,
+    pub resolver: Resolver,
+}
+
+/// Opens a TCP connection, originating from the dialer's local address when one is given.
+pub async fn open_tcp(host: &str, port: u16, dialer: &Dialer) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for ip in dialer.resolver.lookup(host).await? {
+        // A source address can only reach addresses of its own family
+        if dialer.local_address.is_some_and(|local| local.is_ipv4() != ip.is_ipv4()) {
+            continue;
+        }
+        let socket = if ip.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+        if let Some(local) = dialer.local_address {
+            socket.bind(SocketAddr::new(local, 0))?;
+        }
+        match socket.connect(SocketAddr::new(ip, port)).await {
+            Ok(tcp) => return Ok(tcp),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        let message = match dialer.local_address {
+            Some(local) => format!("{} has no address reachable from {}", host, local),
+            None => format!("{} has no addresses", host),
+        };
+        io::Error::new(io::ErrorKind::AddrNotAvailable, message)
+    }))
+}
+
+/// Opens a TCP connection, giving up after the dialer's connect timeout.
+pub async fn connect_tcp(host: &str, port: u16, dialer: &Dialer) -> Result<TcpStream, String> {
+    match timeout(dialer.connect_timeout, open_tcp(host, port, dialer)).await {
+        Ok(Ok(tcp)) => Ok(tcp),
+        Ok(Err(e)) => Err(format!("connection failed: {}", e)),
+        Err(_) => Err("connection timed out".to_string()),
+    }
+}
+
+/// Performs a verified TLS handshake with `host:port`; the connect timeout covers TCP and TLS setup.
+pub async fn connect(
+    connector: &TlsConnector,
+    host: &str,
+    port: u16,
+    dialer: &Dialer,
+) -> Result<TlsStream<TcpStream>, String> {
+    let server_name =
+        ServerName::try_from(host).map_err(|_| format!("invalid TLS server name: {}", host))?;
+    let handshake = async {
+        let tcp = open_tcp(host, port, dialer)
+            .await
+            .map_err(|e| format!("connection failed: {}", e))?;
+        connector
+            .connect(server_name, tcp)
+            .await
+            .map_err(|e| format!("TLS handshake failed: {}", e))
+    };
+    timeout(dialer.connect_timeout, handshake)
+        .await
+        .map_err(|_| "connection timed out".to_string())?
+}
+
+/// SHA-256 fingerprint of the leaf certificate presented by `host:port`.
+pub async fn peer_certificate_sha256(host: &str, port: u16, dialer: &Dialer) -> Result<[u8; 32], String> {
+    let stream = connect(&connector(&[]), host, port, dialer).await?;
+    let (_, connection) = stream.get_ref();
+    let leaf = connection
+        .peer_certificates()
+        .and_then(|certificates| certificates.first())
+        .ok_or("server presented no certificate")?;
+
+    let digest = ring::digest::digest(&ring::digest::SHA256, &leaf.0);
+    let mut fingerprint = [0; 32];
+    fingerprint.copy_from_slice(digest.as_ref());
+    Ok(fingerprint)
+}
+
+/// Parses a hex SHA-256 fingerprint, with or without colons, e.g. `AB:CD:...` or `abcd...`.
+pub fn parse_fingerprint(value: &str) -> Option<[u8; 32]> {
+    let hex: String = value.chars().filter(|c| *c != ':' && !c.is_whitespace()).collect();
+    if hex.len() != 64 {
+        return None;
+    }
+
+    let mut fingerprint = [0; 32];
+    for (index, byte) in fingerprint.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+    Some(fingerprint)
+}
+
+/// Formats a fingerprint the way `openssl x509 -fingerprint` does: uppercase, colon-separated.
+pub fn format_fingerprint(fingerprint: &[u8; 32]) -> String {
+    fingerprint
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
@@ -0,0 +1,71 @@
+//! Bearer tokens written to a file and rotated by an external agent, such as a Vault agent or a
+//! Kubernetes projected service account token.
+//!
+//! The file is checked before every request and only read again when its modification time or size
+//! changed, so a rotated token is picked up by the next check without reading the file each time.
+
+use reqwest::RequestBuilder;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+#[derive(Debug)]
+struct CachedToken {
+    modified: SystemTime,
+    len: u64,
+    value: String,
+}
+
+#[derive(Debug)]
+pub struct TokenFile {
+    path: PathBuf,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl TokenFile {
+    /// Reads the token once up front, so a missing or empty file is a configuration error.
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let file = Self {
+            path: path.to_path_buf(),
+            cached: Mutex::default(),
+        };
+        file.token()?;
+        Ok(file)
+    }
+
+    /// Adds the current token as an `Authorization: Bearer` header.
+    pub fn authorize(&self, request: RequestBuilder) -> Result<RequestBuilder, String> {
+        Ok(request.bearer_auth(self.token()?))
+    }
+
+    /// Forgets the cached token, so the next check reads the file even if it looks unchanged.
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    fn token(&self) -> Result<String, String> {
+        let error = |e: std::io::Error| format!("cannot read bearer token file {}: {}", self.path.display(), e);
+        let metadata = fs::metadata(&self.path).map_err(error)?;
+        let modified = metadata.modified().map_err(error)?;
+
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(token) = cached.as_ref().filter(|token| token.modified == modified && token.len == metadata.len()) {
+            return Ok(token.value.clone());
+        }
+
+        let value = fs::read_to_string(&self.path).map_err(error)?.trim().to_string();
+        if value.is_empty() {
+            return Err(format!("bearer token file {} is empty", self.path.display()));
+        }
+        if cached.is_some() {
+            log::info!("Read the rotated bearer token from {}", self.path.display());
+        }
+        *cached = Some(CachedToken {
+            modified,
+            len: metadata.len(),
+            value: value.clone(),
+        });
+        Ok(value)
+    }
+}
@@ -0,0 +1,209 @@
+//! Custom health logic in a WebAssembly module, for criteria the built-in checks can't express.
+//!
+//! The module gets the response as JSON and answers with a verdict, so it can be written in any
+//! language that compiles to WebAssembly. It runs without any host functions, so it cannot reach the
+//! filesystem, the network or the clock, and every check runs in a fresh instance with limited fuel
+//! and memory.
+//!
+//! The interface, version 1, is three exports:
+//!
+//! - `memory`, the module's linear memory;
+//! - `alloc(len: i32) -> i32`, returning where the host may write `len` bytes of input;
+//! - `check(ptr: i32, len: i32) -> i64`, reading the input JSON and returning the location of the
+//!   output JSON as `ptr << 32 | len`.
+//!
+//! The input is `{"version": 1, "url": ..., "status": 200, "headers": {...}, "body": "..."}`, with
+//! lowercase header names and the values of a repeated header joined with `, `. The output is
+//! `{"status": "up" | "degraded" | "down", "reason": "..."}`, where `reason` is optional.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use wasmtime::{Engine, ExternType, Instance, Module, Store, StoreLimits, StoreLimitsBuilder, Trap, ValType};
+
+/// Version of the input and output layout, passed in the input so a module can check it.
+pub const INTERFACE_VERSION: u32 = 1;
+const DEFAULT_FUEL: u64 = 100_000_000;
+const DEFAULT_MAX_MEMORY_MB: u64 = 64;
+
+/// Compiled modules by path, so URLs sharing a module compile it once.
+static MODULES: Mutex<BTreeMap<PathBuf, Module>> = Mutex::new(BTreeMap::new());
+
+/// One engine for every module, metering fuel.
+fn engine() -> Result<&'static Engine, String> {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    if let Some(engine) = ENGINE.get() {
+        return Ok(engine);
+    }
+    let mut config = wasmtime::Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config).map_err(|e| e.to_string())?;
+    Ok(ENGINE.get_or_init(|| engine))
+}
+
+/// The `wasm_check` option of a URL in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WasmCheckConfig {
+    /// Path of the compiled module, relative to the config file.
+    pub module: PathBuf,
+    /// Instructions a single check may run, roughly; a check that uses them all fails.
+    #[serde(default)]
+    pub fuel: Option<u64>,
+    #[serde(default)]
+    pub max_memory_mb: Option<u64>,
+}
+
+/// The response as the module receives it.
+#[derive(Serialize)]
+pub struct WasmInput<'a> {
+    pub version: u32,
+    pub url: &'a str,
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WasmStatus {
+    Up,
+    Degraded,
+    Down,
+}
+
+/// The module's verdict.
+#[derive(Debug, Deserialize)]
+pub struct WasmVerdict {
+    pub status: WasmStatus,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+pub struct WasmCheck {
+    module: Module,
+    path: PathBuf,
+    fuel: u64,
+    max_memory: usize,
+}
+
+impl std::fmt::Debug for WasmCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmCheck").field("path", &self.path).field("fuel", &self.fuel).finish()
+    }
+}
+
+impl WasmCheck {
+    /// Compiles the module once and verifies it has the exports of the interface and no imports.
+    pub fn new(config: &WasmCheckConfig, base_dir: &Path) -> Result<Self, String> {
+        let fuel = config.fuel.unwrap_or(DEFAULT_FUEL);
+        if fuel == 0 {
+            return Err("fuel must be a positive number".to_string());
+        }
+        let max_memory_mb = config.max_memory_mb.unwrap_or(DEFAULT_MAX_MEMORY_MB);
+        if !(1..=4096).contains(&max_memory_mb) {
+            return Err("max_memory_mb must be a number from 1 to 4096".to_string());
+        }
+
+        let path = base_dir.join(&config.module);
+        let mut modules = MODULES.lock().unwrap_or_else(|e| e.into_inner());
+        let module = match modules.get(&path) {
+            Some(module) => module.clone(),
+            None => {
+                let load = |e: &dyn std::fmt::Display| format!("cannot load {}: {}", path.display(), e);
+                let wasm = fs::read(&path).map_err(|e| load(&e))?;
+                if !wasm.starts_with(b"\0asm") {
+                    return Err(load(&"not a WebAssembly module"));
+                }
+                // Compile errors can span several lines of byte offsets and context
+                let module = Module::new(engine()?, &wasm)
+                    .map_err(|e| load(&e.to_string().lines().next().unwrap_or_default()))?;
+                modules.insert(path.clone(), module.clone());
+                module
+            }
+        };
+
+        if let Some(import) = module.imports().next() {
+            return Err(format!(
+                "{} imports {}.{}, but WASM checks run without host functions",
+                path.display(),
+                import.module(),
+                import.name()
+            ));
+        }
+        let export = |name: &str| module.get_export(name);
+        if !matches!(export("memory"), Some(ExternType::Memory(_))) {
+            return Err(format!("{} does not export its memory as `memory`", path.display()));
+        }
+        let signature = |name: &str, params: &[ValType], results: &[ValType]| match export(name) {
+            Some(ExternType::Func(func)) => {
+                let same = |actual: &[ValType], expected: &[ValType]| {
+                    actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, b)| ValType::eq(a, b))
+                };
+                same(&func.params().collect::<Vec<_>>(), params) && same(&func.results().collect::<Vec<_>>(), results)
+            }
+            _ => false,
+        };
+        if !signature("alloc", &[ValType::I32], &[ValType::I32]) {
+            return Err(format!("{} must export `alloc(len: i32) -> i32`", path.display()));
+        }
+        if !signature("check", &[ValType::I32, ValType::I32], &[ValType::I64]) {
+            return Err(format!("{} must export `check(ptr: i32, len: i32) -> i64`", path.display()));
+        }
+
+        Ok(Self {
+            module,
+            path,
+            fuel,
+            max_memory: (max_memory_mb * 1024 * 1024) as usize,
+        })
+    }
+
+    /// Runs the module on one response in a fresh instance, so no state carries over between checks.
+    ///
+    /// This is CPU-bound and bounded only by fuel, so callers run it on a blocking thread.
+    pub fn run(&self, input: &WasmInput) -> Result<WasmVerdict, String> {
+        let input = serde_json::to_vec(input).map_err(|e| e.to_string())?;
+        let limits = StoreLimitsBuilder::new().memory_size(self.max_memory).instances(1).build();
+        let mut store: Store<StoreLimits> = Store::new(self.module.engine(), limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+
+        let failed = |e: wasmtime::Error| match e.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => format!("used up its fuel of {}", self.fuel),
+            Some(trap) => trap.to_string(),
+            None => e.to_string(),
+        };
+        let instance = Instance::new(&mut store, &self.module, &[]).map_err(failed)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("module has no memory")?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(failed)?;
+        let check = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, "check")
+            .map_err(failed)?;
+
+        let len = i32::try_from(input.len()).map_err(|_| "response too large".to_string())?;
+        let ptr = alloc.call(&mut store, len).map_err(failed)?;
+        memory
+            .write(&mut store, ptr as u32 as usize, &input)
+            .map_err(|_| format!("alloc returned {} bytes at {}, outside its memory", len, ptr))?;
+
+        let packed = check.call(&mut store, (ptr, len)).map_err(failed)? as u64;
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        let output = memory
+            .data(&store)
+            .get(out_ptr..out_ptr.saturating_add(out_len))
+            .ok_or_else(|| format!("check returned {} bytes at {}, outside its memory", out_len, out_ptr))?;
+
+        serde_json::from_slice(output).map_err(|e| format!("invalid verdict: {}", e))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}